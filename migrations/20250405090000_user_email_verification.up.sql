@@ -0,0 +1,4 @@
+-- track email verification
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP;
+-- accounts created before verification existed are trusted
+UPDATE users SET email_verified_at = now();
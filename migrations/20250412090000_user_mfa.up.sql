@@ -0,0 +1,12 @@
+-- TOTP second factor, the secret is pending until enabled_at is set
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled_at TIMESTAMP;
+-- create recovery codes table
+CREATE TABLE user_recovery_codes (
+    id UUID PRIMARY KEY NOT NULL DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX user_recovery_codes_user_id_idx ON user_recovery_codes (user_id);
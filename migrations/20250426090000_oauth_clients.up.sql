@@ -0,0 +1,13 @@
+-- create oauth clients table, public clients have no secret and must use PKCE
+CREATE TABLE oauth_clients (
+    id UUID PRIMARY KEY NOT NULL DEFAULT gen_random_uuid(),
+    client_id TEXT NOT NULL UNIQUE,
+    client_secret_hash TEXT,
+    name TEXT NOT NULL,
+    redirect_uris TEXT NOT NULL DEFAULT '',
+    grant_types TEXT NOT NULL,
+    scopes TEXT NOT NULL DEFAULT '',
+    roles TEXT NOT NULL DEFAULT '',
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
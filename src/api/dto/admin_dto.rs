@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+#[derive(Validate, Debug, Default, Serialize, Deserialize)]
+pub struct RevokeBeforeDto {
+    /// Unix timestamp, tokens issued at or before it are revoked. Defaults to now.
+    #[validate(range(min = 0, message = "before must be a positive timestamp"))]
+    pub before: Option<i64>,
+}
+
+#[derive(Validate, Debug, Default, Serialize, Deserialize)]
+pub struct RevokeTokenDto {
+    /// Expiration time of the revoked token. Defaults to the longest token lifetime from now.
+    #[validate(range(min = 0, message = "exp must be a positive timestamp"))]
+    pub exp: Option<i64>,
+}
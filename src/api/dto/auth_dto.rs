@@ -7,4 +7,87 @@ pub struct LoginUserDto {
     pub identifier: String,
     #[validate(length(min = 3, max = 20, message = "password must be between 3 and 20 characters"))]
     pub password: String,
-}
\ No newline at end of file
+    /// Audience of the issued tokens, such as the admin one, the default audience when missing.
+    pub audience: Option<String>,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct RefreshTokenDto {
+    #[validate(length(min = 1, message = "refresh token cannot be empty"))]
+    pub refresh_token: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct RegisterUserDto {
+    #[validate(length(min = 1, max = 100, message = "name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(custom(function = "crate::application::security::validator::validate_username"))]
+    pub username: String,
+    #[validate(custom(function = "crate::application::security::validator::validate_email_address"))]
+    pub email: String,
+    #[validate(length(min = 8, max = 20, message = "password must be between 8 and 20 characters"))]
+    pub password: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct VerifyEmailDto {
+    #[validate(length(min = 1, message = "token cannot be empty"))]
+    pub token: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct IdentifierDto {
+    #[validate(custom(function = "crate::application::security::validator::validate_identifier"))]
+    pub identifier: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct MagicLinkDto {
+    #[validate(length(min = 1, message = "token cannot be empty"))]
+    pub token: String,
+    /// Audience of the issued tokens, such as the admin one, the default audience when missing.
+    pub audience: Option<String>,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct ResetPasswordDto {
+    #[validate(length(min = 1, message = "token cannot be empty"))]
+    pub token: String,
+    #[validate(length(min = 8, max = 20, message = "password must be between 8 and 20 characters"))]
+    pub password: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct VerifyMfaDto {
+    #[validate(length(min = 1, message = "mfa token cannot be empty"))]
+    pub mfa_token: String,
+    #[validate(length(min = 6, max = 20, message = "code must be between 6 and 20 characters"))]
+    pub code: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct WebAuthnOptionsDto {
+    /// Restricts the ceremony to the credentials of this user, discoverable credentials are used otherwise.
+    #[validate(custom(function = "crate::application::security::validator::validate_identifier"))]
+    pub identifier: Option<String>,
+}
+
+/// Credential returned by `navigator.credentials.get()`, as serialized by `PublicKeyCredential.toJSON()`.
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct WebAuthnLoginDto {
+    #[validate(length(min = 1, message = "credential id cannot be empty"))]
+    pub id: String,
+    pub response: WebAuthnAssertionDto,
+    /// Audience of the issued tokens, such as the admin one, the default audience when missing.
+    pub audience: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnAssertionDto {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+    pub user_handle: Option<String>,
+}
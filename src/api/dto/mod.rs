@@ -1,2 +1,4 @@
+pub mod admin_dto;
 pub mod auth_dto;
+pub mod oauth_dto;
 pub mod user_dto;
\ No newline at end of file
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+use crate::application::{security::role::Roles, service::oauth_service::AuthorizationRequest};
+use crate::domain::entities::user::User;
+use crate::domain::entities::oauth_client::{
+    GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS, GRANT_DEVICE_CODE, GRANT_REFRESH_TOKEN,
+};
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct AuthorizeDto {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+impl AuthorizeDto {
+    pub fn request(&self) -> AuthorizationRequest<'_> {
+        AuthorizationRequest {
+            response_type: &self.response_type,
+            client_id: &self.client_id,
+            redirect_uri: &self.redirect_uri,
+            scope: self.scope.as_deref(),
+            code_challenge: self.code_challenge.as_deref(),
+            code_challenge_method: self.code_challenge_method.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizeResponseDto {
+    /// Where the consent page sends the user agent, carrying the code or the error.
+    pub redirect_uri: String,
+}
+
+/// Form parameters of the token endpoint, which ones are required depends on `grant_type`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenRequestDto {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub device_code: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Form parameters of the introspection endpoint, see RFC 7662 section 2.1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IntrospectionRequestDto {
+    pub token: String,
+    /// Accepted for compatibility, every kind of token is looked up anyway.
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Claims about the signed-in user, named after OpenID Connect Core section 5.1.
+#[derive(Debug, Serialize)]
+pub struct UserInfoDto {
+    pub sub: String,
+    pub name: String,
+    pub preferred_username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub roles: Roles,
+    /// Unix timestamp of the last profile update.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
+}
+
+impl From<User> for UserInfoDto {
+    fn from(user: User) -> Self {
+        Self {
+            sub: user.id.to_string(),
+            name: user.name,
+            preferred_username: user.username,
+            email: user.email,
+            email_verified: user.email_verified_at.is_some(),
+            roles: Roles::parse(&user.roles),
+            updated_at: user.updated_at.map(|updated_at| updated_at.and_utc().timestamp()),
+        }
+    }
+}
+
+/// Form parameters of the device authorization endpoint, see RFC 8628 section 3.1.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceCodeRequestDto {
+    pub scope: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct DeviceApprovalDto {
+    #[validate(length(min = 1, max = 16, message = "user_code must be between 1 and 16 characters"))]
+    pub user_code: String,
+    /// Denies the request when false, the device then stops polling.
+    pub approved: bool,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+#[validate(schema(function = "validate_oauth_client"))]
+pub struct CreateOAuthClientDto {
+    #[validate(length(min = 1, max = 100, message = "name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Vec<String>,
+    #[validate(custom(function = "crate::application::security::validator::validate_scopes"))]
+    pub scopes: Option<Vec<String>>,
+    /// Roles of the tokens issued by the `client_credentials` grant, comma separated.
+    pub roles: Option<String>,
+    /// Confidential clients get a secret, public clients such as SPAs rely on PKCE only.
+    pub confidential: bool,
+}
+
+fn validate_oauth_client(client: &CreateOAuthClientDto) -> Result<(), ValidationError> {
+    let error = |code: &'static str, message: String| {
+        let mut err = ValidationError::new(code);
+        err.message = Some(message.into());
+        Err(err)
+    };
+
+    let known_grants = [GRANT_AUTHORIZATION_CODE, GRANT_REFRESH_TOKEN, GRANT_CLIENT_CREDENTIALS, GRANT_DEVICE_CODE];
+    if let Some(grant_type) = client.grant_types.iter().find(|grant_type| !known_grants.contains(&grant_type.as_str())) {
+        return error("invalid_grant_type", format!("unsupported grant type: {}", grant_type));
+    }
+    if client.grant_types.iter().any(|grant_type| grant_type == GRANT_CLIENT_CREDENTIALS) && !client.confidential {
+        return error("public_client_credentials", "client_credentials requires a confidential client".to_owned());
+    }
+    if client.grant_types.iter().any(|grant_type| grant_type == GRANT_AUTHORIZATION_CODE) && client.redirect_uris.is_empty() {
+        return error("missing_redirect_uri", "authorization_code requires at least one redirect URI".to_owned());
+    }
+    if let Some(uri) = client.redirect_uris.iter().find(|uri| !(uri.starts_with("https://") || uri.starts_with("http://")) || uri.contains(',') || uri.contains('#')) {
+        return error("invalid_redirect_uri", format!("invalid redirect URI: {}", uri));
+    }
+    Ok(())
+}
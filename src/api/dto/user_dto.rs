@@ -1,6 +1,7 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use crate::domain::entities::user::User;
+use validator::Validate;
+use crate::domain::entities::{session::Session, user::User};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FilterUserDto {
@@ -23,4 +24,62 @@ impl FilterUserDto {
             updated_at: user.created_at.unwrap(),
         }
     }
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct ChangePasswordDto {
+    #[validate(length(min = 3, max = 20, message = "password must be between 3 and 20 characters"))]
+    pub current_password: String,
+    #[validate(length(min = 8, max = 20, message = "password must be between 8 and 20 characters"))]
+    pub new_password: String,
+    /// Whether the session making the change stays signed in, defaults to `true`.
+    pub keep_current_session: Option<bool>,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct MfaCodeDto {
+    /// A TOTP code, or a recovery code where both are accepted.
+    #[validate(length(min = 6, max = 20, message = "code must be between 6 and 20 characters"))]
+    pub code: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct RegisterWebAuthnCredentialDto {
+    #[validate(length(min = 1, max = 100, message = "name must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Credential returned by `navigator.credentials.create()`, as serialized by `PublicKeyCredential.toJSON()`.
+    pub credential: WebAuthnRegistrationDto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebAuthnRegistrationDto {
+    pub id: String,
+    pub response: WebAuthnAttestationDto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebAuthnAttestationDto {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyDto {
+    #[validate(length(min = 1, max = 100, message = "name must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Permissions the key is restricted to, a key without scopes can only identify its owner.
+    #[validate(custom(function = "crate::application::security::validator::validate_scopes"))]
+    pub scopes: Option<Vec<String>>,
+    #[validate(range(min = 1, max = 3650, message = "expiry must be between 1 and 3650 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionDto {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request.
+    pub current: bool,
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use std::fmt::{Display, Formatter};
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::{
     Json,
     response::{IntoResponse, Response},
@@ -11,7 +11,10 @@ use validator::{ValidationErrors};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiError {
     pub status: u16,
-    pub errors: Vec<ApiErrorResponse>
+    pub errors: Vec<ApiErrorResponse>,
+    /// Seconds the client should wait before retrying, sent as the `Retry-After` header.
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
 }
 
 impl ApiError {
@@ -21,6 +24,7 @@ impl ApiError {
             errors: vec![
                 ApiErrorResponse::new("invalid json request"),
             ],
+            retry_after: None,
         }
     }
 
@@ -40,7 +44,7 @@ impl ApiError {
 
             ApiErrorResponse {
                 message: format!("{}: {}", field, messages.join(", ")),
-                code: errors.get(0).map(|e| e.code.to_string()),
+                code: errors.first().map(|e| e.code.to_string()),
                 timestamp: Utc::now(),
                 ..Default::default()
             }
@@ -49,15 +53,24 @@ impl ApiError {
         Self {
             status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
             errors,
+            retry_after: None,
         }
     }
+
+    pub fn retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         // tracing::error!("error response: {:?}", self);
         let status_code = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        (status_code, Json(self)).into_response()
+        match self.retry_after {
+            Some(seconds) => (status_code, [(header::RETRY_AFTER, seconds.to_string())], Json(self)).into_response(),
+            None => (status_code, Json(self)).into_response(),
+        }
     }
 }
 
@@ -67,6 +80,7 @@ impl From<(StatusCode, ApiErrorResponse)> for ApiError {
         Self {
             status: status_code.as_u16(),
             errors: vec![error_response],
+            retry_after: None,
         }
     }
 }
@@ -79,7 +93,8 @@ impl From<sqlx::Error> for ApiError {
         };
         Self {
             status: status_code.as_u16(),
-            errors: vec![ApiErrorResponse::from(error)]
+            errors: vec![ApiErrorResponse::from(error)],
+            retry_after: None,
         }
     }
 }
@@ -197,13 +212,24 @@ pub enum ApiErrorCode {
     AuthenticationMissingCredentials,
     AuthenticationTokenCreationError,
     AuthenticationHashingPasswordError,
+    AuthenticationHashingOverloaded,
     AuthenticationInvalidToken,
     AuthenticationForbidden,
+    AuthenticationAccountLocked,
+    AuthenticationInvalidAudience,
+    AuthenticationInvalidMfaCode,
+    AuthenticationMfaAlreadyEnabled,
+    AuthenticationMfaNotEnrolled,
+    AuthenticationSessionLimitReached,
+    AuthenticationInvalidWebauthnResponse,
     UserNotFound,
+    UserAlreadyExists,
+    SessionNotFound,
     ResourceNotFound,
     ApiVersionError,
     DatabaseError,
     RedisError,
+    MailError,
 }
 
 impl Display for ApiErrorCode {
@@ -1,5 +1,8 @@
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
-use axum::{extract::{FromRef, FromRequestParts}, http::request::Parts, RequestPartsExt};
+use axum::{extract::{ConnectInfo, FromRef, FromRequestParts}, http::{header, request::Parts}, RequestPartsExt};
 use axum_extra::{
     TypedHeader,
     headers::{Authorization, authorization::Bearer},
@@ -9,8 +12,11 @@ use crate::application::{
     state::{SharedState, AppState},
     security::{
         auth::AuthError,
-        jwt::{AccessClaim, ClaimsMethods, decode_token},
+        jwt::{AccessClaim, ClaimsMethods, JwtTokenType, RequiredAudience, decode_token},
+        policy::{RequiredPermission, Resource},
+        role::RequiredRole,
     },
+    service::{api_key_service, session_service::{self, ClientInfo}},
 };
 use crate::application::security::auth;
 
@@ -22,16 +28,194 @@ where
     type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        decode_token_from_request_part(parts, state).await
+        // Claims already validated by a route guard, see `middleware::require_audience`.
+        if let Some(claims) = parts.extensions.get::<Self>() {
+            return Ok(claims.clone())
+        }
+
+        let bearer = bearer_token_from_request_part(parts).await?;
+        let claims: Self = if api_key_service::is_api_key(&bearer) {
+            let state: Arc<AppState> = Arc::from_ref(state);
+            api_key_service::authenticate(&bearer, &state).await?
+        } else {
+            decode_token_from_request_part(&bearer, state).await?
+        };
+
+        // Refresh tokens must never be accepted in place of access tokens.
+        if claims.get_typ() != JwtTokenType::AccessToken {
+            tracing::error!("unexpected token type: {:?}", claims.get_typ());
+            return Err(AuthError::InvalidToken.into())
+        }
+
+        if claims.is_impersonated() {
+            tracing::warn!("impersonated request: {} acting as {}, token {}", claims.actor_sub(), claims.sub, claims.jti);
+        } else if !claims.is_delegated() {
+            let state: Arc<AppState> = Arc::from_ref(state);
+            session_service::touch_session(&claims.fid, &state)
+                .await
+                .map_err(AuthError::from)?;
+        }
+        Ok(claims)
     }
 }
 
-async fn decode_token_from_request_part<S, T>(parts: &mut Parts, state: &S) -> Result<T, ApiError>
+/// Access claims of a signed-in user, API keys and OAuth client tokens are rejected with a 403.
+///
+/// Guards account management endpoints a delegated token must not reach, such as creating more keys.
+pub struct RequireSession(pub AccessClaim);
+
+impl<S> FromRequestParts<S> for RequireSession
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = AccessClaim::from_request_parts(parts, state).await?;
+        if claims.is_delegated() {
+            tracing::error!("delegated token used on a session only endpoint: {}", claims.jti);
+            return Err(AuthError::Forbidden.into())
+        }
+        Ok(Self(claims))
+    }
+}
+
+/// Access claims of a signed-in user acting as themselves, delegated and impersonated tokens are rejected with a 403.
+///
+/// Guards endpoints only the account owner may reach, such as changing the password.
+pub struct RequireSelf(pub AccessClaim);
+
+impl<S> FromRequestParts<S> for RequireSelf
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let RequireSession(claims) = RequireSession::from_request_parts(parts, state).await?;
+        if claims.is_impersonated() {
+            tracing::error!("impersonated token of {} used on an owner only endpoint: {}", claims.actor_sub(), claims.jti);
+            return Err(AuthError::Forbidden.into())
+        }
+        Ok(Self(claims))
+    }
+}
+
+/// Access claims of a subject holding the role `R`, rejects the request with a 403 otherwise.
+pub struct RequireRole<R: RequiredRole>(pub AccessClaim, pub PhantomData<R>);
+
+impl<S, R> FromRequestParts<S> for RequireRole<R>
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+    R: RequiredRole,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = AccessClaim::from_request_parts(parts, state).await?;
+        auth::require_role(&claims, &R::ROLE)?;
+        Ok(Self(claims, PhantomData))
+    }
+}
+
+/// Access claims of a subject granted the permission `P` on any resource, rejects the request with a 403 otherwise.
+///
+/// Ownership based checks need the resource and are done by handlers through [`crate::application::security::policy::Policy`].
+pub struct RequirePermission<P: RequiredPermission>(pub AccessClaim, pub PhantomData<P>);
+
+impl<S, P> FromRequestParts<S> for RequirePermission<P>
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+    P: RequiredPermission,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = AccessClaim::from_request_parts(parts, state).await?;
+        let state: Arc<AppState> = Arc::from_ref(state);
+        state.config.policy.authorize(&claims, P::PERMISSION, &Resource::any())?;
+        Ok(Self(claims, PhantomData))
+    }
+}
+
+/// Access claims of a token issued for the audience `A`, rejects the request with a 401 otherwise.
+pub struct RequireAudience<A: RequiredAudience>(pub AccessClaim, pub PhantomData<A>);
+
+impl<S, A> FromRequestParts<S> for RequireAudience<A>
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+    A: RequiredAudience,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let claims = AccessClaim::from_request_parts(parts, state).await?;
+        let state: Arc<AppState> = Arc::from_ref(state);
+        if let Some(audience) = A::audience(&state.config) {
+            auth::require_audience(&claims, audience)?;
+        }
+        Ok(Self(claims, PhantomData))
+    }
+}
+
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ClientIp(ip) = ClientIp::from_request_parts(parts, state).await?;
+        let user_agent = parts.headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+/// Address of the client, taken from the first `X-Forwarded-For` hop when proxy headers are trusted.
+pub struct ClientIp(pub IpAddr);
+
+impl<S> FromRequestParts<S> for ClientIp
 where
     SharedState: FromRef<S>,
     S: Send + Sync,
-    T: for<'de> serde::Deserialize<'de> + std::fmt::Debug + ClaimsMethods + Sync + Send,
 {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let state: Arc<AppState> = Arc::from_ref(state);
+
+        if state.config.trust_proxy_headers {
+            let forwarded = parts.headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|value| value.trim().parse::<IpAddr>().ok());
+            if let Some(ip) = forwarded {
+                return Ok(Self(ip))
+            }
+        }
+
+        match parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => Ok(Self(addr.ip())),
+            None => {
+                tracing::warn!("client address unavailable");
+                Ok(Self(IpAddr::V4(Ipv4Addr::UNSPECIFIED)))
+            }
+        }
+    }
+}
+
+async fn bearer_token_from_request_part(parts: &mut Parts) -> Result<String, ApiError> {
     // Extract the token from the authorization header.
     let TypedHeader(Authorization(bearer)) = parts
         .extract::<TypedHeader<Authorization<Bearer>>>()
@@ -41,11 +225,20 @@ where
             AuthError::InvalidAuthorizationHeader
         })?;
 
+    Ok(bearer.token().to_owned())
+}
+
+async fn decode_token_from_request_part<S, T>(token: &str, state: &S) -> Result<T, ApiError>
+where
+    SharedState: FromRef<S>,
+    S: Send + Sync,
+    T: for<'de> serde::Deserialize<'de> + std::fmt::Debug + ClaimsMethods + Sync + Send,
+{
     // Take the state from a reference.
     let state: Arc<AppState> = Arc::from_ref(state);
 
     // Decode the token.
-    let claims = decode_token::<T>(bearer.token(), &state.config)?;
+    let claims = decode_token::<T>(token, &state.config)?;
 
     // Check for revoked tokens if enabled by configuration.
     if state.config.jwt_enable_revoked_tokens {
@@ -0,0 +1,272 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use std::net::IpAddr;
+use uuid::Uuid;
+use crate::api::{
+    ApiError, ApiVersion,
+    dto::{admin_dto::{RevokeBeforeDto, RevokeTokenDto}, oauth_dto::CreateOAuthClientDto},
+    extractor::{RequirePermission, RequireSelf},
+};
+use crate::application::{
+    security::{
+        auth::{self, AuthError},
+        policy::{OAuthClientsManage, TokensRevoke, UsersImpersonate, UsersUnlock},
+        role::{Role, Roles},
+        validator::ValidatedJson,
+    },
+    service::{login_attempt_service, oauth_service, token_service},
+    repository::{oauth_client_repository::OAuthClientRepositoryExt, user_repository::UserRepositoryExt},
+    state::SharedState,
+};
+
+pub async fn list_revocations_handler(
+    api_version: ApiVersion,
+    RequirePermission(_, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} list revocations", api_version);
+
+    let revocations = token_service::list_revocations(&state)
+        .await
+        .map_err(AuthError::from)?;
+
+    Ok(Json(revocations))
+}
+
+pub async fn clear_revocations_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} clear revocations", api_version);
+
+    token_service::clear_revocations(&state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("all revocations cleared by {}", access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_global_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<RevokeBeforeDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} revoke global", api_version);
+
+    let before = body.before.unwrap_or_else(|| chrono::Utc::now().timestamp()) as usize;
+    token_service::revoke_global_tokens_before(before, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("tokens issued before {} globally revoked by {}", before, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unrevoke_global_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} unrevoke global", api_version);
+
+    token_service::unrevoke_global_tokens(&state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("global revocation cleared by {}", access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_user_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+    Path((_, user_id)): Path<(String, Uuid)>,
+    ValidatedJson(body): ValidatedJson<RevokeBeforeDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} revoke user", api_version);
+
+    let before = body.before.unwrap_or_else(|| chrono::Utc::now().timestamp()) as usize;
+    token_service::revoke_user_tokens_before(&user_id.to_string(), before, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("tokens of user {} issued before {} revoked by {}", user_id, before, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unrevoke_user_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+    Path((_, user_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} unrevoke user", api_version);
+
+    token_service::unrevoke_user_tokens(&user_id.to_string(), &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("revocation of user {} cleared by {}", user_id, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_token_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+    Path((_, jti)): Path<(String, String)>,
+    ValidatedJson(body): ValidatedJson<RevokeTokenDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} revoke token", api_version);
+
+    let exp = body.exp.unwrap_or_else(|| {
+        let lifetime = state.config.jwt_exp_access_token_second.max(state.config.jwt_exp_refresh_token_second);
+        chrono::Utc::now().timestamp() + lifetime
+    }) as usize;
+    token_service::revoke_token_id(&jti, exp, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("token {} revoked by {}", jti, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unrevoke_token_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<TokensRevoke>,
+    State(state): State<SharedState>,
+    Path((_, jti)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} unrevoke token", api_version);
+
+    token_service::unrevoke_token_id(&jti, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("revocation of token {} cleared by {}", jti, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlock_identifier_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<UsersUnlock>,
+    State(state): State<SharedState>,
+    Path((_, identifier)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} unlock identifier", api_version);
+
+    // Failures of an existing account are counted under its user ID, whichever identifier was typed.
+    let user = state.get_user_by_identifier(&identifier)
+        .await
+        .map_err(AuthError::from)?;
+    let identifier = login_attempt_service::attempt_identifier(&identifier, user.as_ref());
+    login_attempt_service::unlock_identifier(&identifier, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("login lockout of {} cleared by {}", identifier, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn unlock_ip_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<UsersUnlock>,
+    State(state): State<SharedState>,
+    Path((_, ip)): Path<(String, IpAddr)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} unlock ip", api_version);
+
+    login_attempt_service::unlock_ip(ip, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("login lockout of ip {} cleared by {}", ip, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_oauth_clients_handler(
+    api_version: ApiVersion,
+    RequirePermission(_, _): RequirePermission<OAuthClientsManage>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} list oauth clients", api_version);
+
+    let clients = state.list_oauth_clients().await?;
+
+    Ok(Json(clients))
+}
+
+/// Registers an OAuth client, its secret is only returned in this response.
+pub async fn create_oauth_client_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<OAuthClientsManage>,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<CreateOAuthClientDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} create oauth client", api_version);
+
+    let client = oauth_service::register_client(
+        &body.name,
+        &body.redirect_uris,
+        &body.grant_types,
+        &body.scopes.unwrap_or_default(),
+        body.roles.as_deref().unwrap_or_default(),
+        body.confidential,
+        &state,
+    ).await?;
+    tracing::info!("oauth client {} created by {}", client.client.client_id, access_claim.sub);
+
+    Ok((StatusCode::CREATED, Json(client)))
+}
+
+pub async fn delete_oauth_client_handler(
+    api_version: ApiVersion,
+    RequirePermission(access_claim, _): RequirePermission<OAuthClientsManage>,
+    State(state): State<SharedState>,
+    Path((_, client_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} delete oauth client", api_version);
+
+    state.delete_oauth_client(&client_id).await?;
+    tracing::info!("oauth client {} deleted by {}", client_id, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mints a short-lived token acting as the user, for support staff debugging their account.
+///
+/// Admins cannot be impersonated, and an impersonation token cannot be used to impersonate again.
+pub async fn impersonate_user_handler(
+    api_version: ApiVersion,
+    RequirePermission(_, _): RequirePermission<UsersImpersonate>,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    Path((_, user_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} impersonate user", api_version);
+
+    let user = state.get_user_by_id(user_id).await?;
+    if user.id.to_string() == access_claim.sub || Roles::parse(&user.roles).contains(&Role::Admin) || !user.active {
+        tracing::error!("impersonation of user {} refused to {}", user.id, access_claim.sub);
+        return Err(AuthError::Forbidden.into())
+    }
+
+    let token = auth::create_impersonation_token(&user, &access_claim.sub, &state.config)?;
+    tracing::warn!(
+        "user {} impersonated by {} until {}, token {}",
+        user.id,
+        access_claim.sub,
+        token.access_claim.exp,
+        token.access_claim.jti,
+    );
+
+    Ok((StatusCode::CREATED, Json(token)))
+}
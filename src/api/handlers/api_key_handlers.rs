@@ -0,0 +1,67 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+use crate::api::{ApiError, ApiVersion, dto::user_dto::CreateApiKeyDto, extractor::{RequireSelf, RequireSession}};
+use crate::application::{
+    repository::api_key_repository::ApiKeyRepositoryExt,
+    security::{
+        auth::AuthError,
+        jwt::ClaimsMethods,
+        validator::ValidatedJson,
+    },
+    service::api_key_service,
+    state::SharedState,
+};
+
+pub async fn list_api_keys_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} list api keys", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let api_keys = state.list_api_keys(user_id).await?;
+
+    Ok(Json(api_keys))
+}
+
+/// Creates an API key, the key is only returned in this response.
+pub async fn create_api_key_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<CreateApiKeyDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} create api key", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let api_key = api_key_service::create_api_key(
+        user_id,
+        &body.name,
+        &body.scopes.unwrap_or_default(),
+        body.expires_in_days,
+        &state,
+    ).await?;
+
+    Ok((StatusCode::CREATED, Json(api_key)))
+}
+
+pub async fn revoke_api_key_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+    Path((_, api_key_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} revoke api key", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    state.revoke_api_key(user_id, api_key_id).await?;
+    tracing::info!("api key {} revoked by {}", api_key_id, user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,47 +1,370 @@
-use axum::{extract::{State}, response::IntoResponse, Json};
-use serde_json::json;
-use crate::api::{ApiError, ApiVersion, dto::auth_dto::LoginUserDto};
+use axum::{extract::{State}, http::StatusCode, response::{IntoResponse, Response}, Json};
+use thiserror::Error;
+use crate::api::{
+    ApiError, ApiErrorCode, ApiErrorKind, ApiErrorResponse, ApiVersion,
+    extractor::{RequireSelf, RequireSession},
+    dto::auth_dto::{IdentifierDto, LoginUserDto, MagicLinkDto, RefreshTokenDto, RegisterUserDto, ResetPasswordDto, VerifyEmailDto, VerifyMfaDto},
+};
 use crate::application::{
     state::SharedState,
     security::{
         validator::ValidatedJson,
         auth::{AuthError},
+        jwt::ClaimsMethods,
+        role::Role,
     },
+    service::{email_service, login_attempt_service, magic_link_service, mfa_service, password_service, session_service::{self, ClientInfo}, token_service},
     repository::{
         user_repository::UserRepositoryExt,
     },
 };
 use crate::application::security::{auth, password};
+use crate::domain::entities::user::{NewUser, User};
 
 #[tracing::instrument(level = tracing::Level::TRACE, name = "login", skip_all, fields(identifier=body.identifier))]
 pub async fn login_handler(
     api_version: ApiVersion,
     State(state): State<SharedState>,
+    client: ClientInfo,
     ValidatedJson(body): ValidatedJson<LoginUserDto>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     tracing::trace!("api version: {} login", api_version);
 
-    let data = state.get_user_by_identifier(&body.identifier).await?;
+    let user = state.get_user_by_identifier(&body.identifier)
+        .await
+        .map_err(AuthError::from)?;
+    let identifier = login_attempt_service::attempt_identifier(&body.identifier, user.as_ref());
+    login_attempt_service::check_lockout(&identifier, client.ip, &state).await?;
+
+    let delay = login_attempt_service::failure_delay(&identifier, &state)
+        .await
+        .map_err(AuthError::from)?;
+    if !delay.is_zero() {
+        tracing::debug!("delaying login by {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+
+    let user = match check_credentials(user, &body.password, &state).await {
+        Ok(user) => user,
+        Err(AuthError::WrongCredentials) => {
+            login_attempt_service::record_failure(&identifier, client.ip, &state)
+                .await
+                .map_err(AuthError::from)?;
+            return Err(AuthError::WrongCredentials.into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    login_attempt_service::record_success(&identifier, &state)
+        .await
+        .map_err(AuthError::from)?;
 
-    if data.is_none() {
-        return Err(AuthError::WrongCredentials.into())
+    let audience = auth::token_audience(body.audience.as_deref(), &state.config)?;
+    if user.totp_enabled_at.is_some() {
+        let challenge = auth::create_mfa_challenge(&user, &audience, &state.config)?;
+        return Ok(Json(challenge).into_response())
     }
 
-    let user = data.ok_or(AuthError::WrongCredentials)?;
+    let token = auth::issue_token(&user, &audience, &client, &state).await?;
+
+    Ok(Json(token).into_response())
+}
+
+/// Exchanges the token returned by a login with two-factor authentication for a token pair.
+#[tracing::instrument(level = tracing::Level::TRACE, name = "verify_mfa", skip_all)]
+pub async fn verify_mfa_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<VerifyMfaDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} verify mfa", api_version);
+
+    let (user, audience) = mfa_service::verify_mfa(&body.mfa_token, &body.code, client.ip, &state).await?;
+    let token = auth::issue_token(&user, &audience, &client, &state).await?;
+
+    Ok(Json(token))
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "request_magic_link", skip_all, fields(identifier=body.identifier))]
+pub async fn request_magic_link_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<IdentifierDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} request magic link", api_version);
+
+    // Always accept the request so the endpoint cannot be used to discover accounts, the lookup and the email
+    // happen in the background so the response time does not tell either.
+    tokio::spawn(async move {
+        if let Err(e) = magic_link_service::request_magic_link(&body.identifier, &state).await {
+            tracing::error!("could not process magic link request: {}", e);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Exchanges a magic link for a token pair, or for an MFA challenge when two-factor authentication is enabled.
+#[tracing::instrument(level = tracing::Level::TRACE, name = "consume_magic_link", skip_all)]
+pub async fn consume_magic_link_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<MagicLinkDto>,
+) -> Result<Response, ApiError> {
+    tracing::trace!("api version: {} consume magic link", api_version);
+
+    let user = magic_link_service::consume_magic_link(&body.token, &state).await?;
+    login_attempt_service::check_user_lockout(&user, client.ip, &state).await?;
 
-    if !user.active {
-        return Err(AuthError::WrongCredentials.into())
+    // The link only proves access to the mailbox, it stands in for the password and not for the second factor.
+    let audience = auth::token_audience(body.audience.as_deref(), &state.config)?;
+    if user.totp_enabled_at.is_some() {
+        let challenge = auth::create_mfa_challenge(&user, &audience, &state.config)?;
+        return Ok(Json(challenge).into_response())
     }
 
-    let password_matches = password::compare(&body.password, &user.password_hash)
-        .map_err(|_| AuthError::WrongCredentials)?;
+    let token = auth::issue_token(&user, &audience, &client, &state).await?;
+
+    Ok(Json(token).into_response())
+}
+
+async fn check_credentials(user: Option<User>, password: &str, state: &SharedState) -> Result<User, AuthError> {
+    let user = user.ok_or(AuthError::WrongCredentials)?;
+
+    if !user.active || user.email_verified_at.is_none() {
+        return Err(AuthError::WrongCredentials)
+    }
+
+    let password_matches = match state.hasher.compare(password, &user.password_hash).await {
+        Ok(password_matches) => password_matches,
+        Err(e @ AuthError::HashingOverloaded(_)) => return Err(e),
+        Err(_) => return Err(AuthError::WrongCredentials),
+    };
 
     if !password_matches {
-        return Err(AuthError::WrongCredentials)?
+        return Err(AuthError::WrongCredentials)
+    }
+
+    upgrade_password_hash(&user, password, state).await;
+
+    Ok(user)
+}
+
+/// Re-hashes a verified password whose hash is weaker than the current policy.
+///
+/// The login goes on with the old hash when this fails, it is attempted again on the next one.
+async fn upgrade_password_hash(user: &User, password: &str, state: &SharedState) {
+    let policy = &state.config.password_hash_policy;
+    if !password::needs_rehash(&user.password_hash, policy) {
+        return
+    }
+
+    let new_hash = match state.hasher.hash(password, policy).await {
+        Ok(new_hash) => new_hash,
+        Err(e) => {
+            tracing::error!("could not re-hash password of user {}: {}", user.id, e);
+            return
+        }
+    };
+    match state.upgrade_password_hash(user.id, &user.password_hash, &new_hash).await {
+        Ok(true) => tracing::info!("password hash of user {} upgraded", user.id),
+        Ok(false) => tracing::debug!("password of user {} changed before its hash could be upgraded", user.id),
+        Err(e) => tracing::error!("could not store upgraded password hash of user {}: {}", user.id, e),
     }
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "register", skip_all, fields(username=body.username))]
+pub async fn register_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<RegisterUserDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} register", api_version);
+
+    let taken_fields = state.find_taken_user_fields(&body.username, &body.email).await?;
+    if !taken_fields.is_empty() {
+        return Err(RegisterError::AlreadyTaken(taken_fields).into())
+    }
+
+    let new_user = NewUser {
+        name: body.name,
+        username: body.username,
+        email: body.email,
+        password_hash: state.hasher.hash(body.password, &state.config.password_hash_policy).await?,
+        roles: Role::User.to_string(),
+    };
+
+    let user = state.create_user(&new_user)
+        .await
+        .map_err(|e| RegisterError::from_unique_violation(&e).map_or_else(|| ApiError::from(e), ApiError::from))?;
+    tracing::info!("user {} registered", user.id);
+
+    // The account exists at this point, a failed delivery is recovered through the resend endpoint.
+    if let Err(e) = email_service::send_verification_email(&user, &state).await {
+        tracing::error!("could not send verification email to user {}: {}", user.id, e);
+    }
+
+    Ok((StatusCode::CREATED, Json(user)))
+}
 
-    let token = auth::create_token(user, &state.config);
-    let access_token = token.access_token;
+#[tracing::instrument(level = tracing::Level::TRACE, name = "verify_email", skip_all)]
+pub async fn verify_email_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<VerifyEmailDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} verify email", api_version);
+
+    let user = email_service::verify_email(&body.token, &state).await?;
+
+    Ok(Json(user))
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "resend_verification_email", skip_all, fields(identifier=body.identifier))]
+pub async fn resend_verification_email_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<IdentifierDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} resend verification email", api_version);
+
+    // Always accept the request so the endpoint cannot be used to discover accounts, the lookup and the email
+    // happen in the background so the response time does not tell either.
+    tokio::spawn(async move {
+        match state.get_user_by_identifier(&body.identifier).await {
+            Ok(Some(user)) if user.active && user.email_verified_at.is_none() => {
+                if let Err(e) = email_service::send_verification_email(&user, &state).await {
+                    tracing::error!("could not send verification email to user {}: {}", user.id, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("could not process verification email request: {}", e),
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "forgot_password", skip_all, fields(identifier=body.identifier))]
+pub async fn forgot_password_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<IdentifierDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} forgot password", api_version);
+
+    // Always accept the request so the endpoint cannot be used to discover accounts, the lookup and the email
+    // happen in the background so the response time does not tell either.
+    tokio::spawn(async move {
+        if let Err(e) = password_service::request_password_reset(&body.identifier, &state).await {
+            tracing::error!("could not process password reset request: {}", e);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "reset_password", skip_all)]
+pub async fn reset_password_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<ResetPasswordDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} reset password", api_version);
+
+    password_service::reset_password(&body.token, &body.password, &state).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    Ok(Json(json!({"token": access_token})))
-}
\ No newline at end of file
+#[tracing::instrument(level = tracing::Level::TRACE, name = "refresh", skip_all)]
+pub async fn refresh_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<RefreshTokenDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} refresh", api_version);
+
+    let token = auth::refresh_token(&body.refresh_token, &state).await?;
+
+    Ok(Json(token))
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "logout", skip_all, fields(sub=access_claim.sub))]
+pub async fn logout_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} logout", api_version);
+
+    token_service::revoke_token(&access_claim, &state)
+        .await
+        .map_err(AuthError::from)?;
+    token_service::revoke_refresh_family(&access_claim.fid, &state)
+        .await
+        .map_err(AuthError::from)?;
+    session_service::remove_session(&access_claim.sub, &access_claim.fid, &state)
+        .await
+        .map_err(AuthError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(level = tracing::Level::TRACE, name = "logout_all", skip_all, fields(sub=access_claim.sub))]
+pub async fn logout_all_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} logout all", api_version);
+
+    token_service::revoke_user_tokens(access_claim.get_sub(), &state)
+        .await
+        .map_err(AuthError::from)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Error)]
+enum RegisterError {
+    #[error("already taken: {}", .0.join(", "))]
+    AlreadyTaken(Vec<&'static str>),
+}
+
+impl RegisterError {
+    /// Registrations racing for the same username or email end up on the unique constraints.
+    fn from_unique_violation(e: &sqlx::Error) -> Option<Self> {
+        let sqlx::Error::Database(db_error) = e else {
+            return None
+        };
+        if !db_error.is_unique_violation() {
+            return None
+        }
+        let field = match db_error.constraint() {
+            Some("users_username_key") => "username",
+            Some("users_email_key") => "email",
+            _ => return None,
+        };
+        Some(Self::AlreadyTaken(vec![field]))
+    }
+}
+
+impl From<RegisterError> for ApiError {
+    fn from(register_error: RegisterError) -> Self {
+        match register_error {
+            RegisterError::AlreadyTaken(fields) => Self {
+                status: StatusCode::CONFLICT.as_u16(),
+                errors: fields.iter().map(|field| {
+                    ApiErrorResponse::new(&format!("{}: already taken", field))
+                        .code(ApiErrorCode::UserAlreadyExists)
+                        .kind(ApiErrorKind::ValidationError)
+                        .detail(serde_json::json!({"field": field}))
+                }).collect(),
+                retry_after: None,
+            },
+        }
+    }
+}
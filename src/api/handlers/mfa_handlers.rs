@@ -0,0 +1,83 @@
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+};
+use crate::api::{ApiError, ApiVersion, dto::user_dto::MfaCodeDto, extractor::RequireSelf};
+use crate::application::{
+    repository::user_repository::UserRepositoryExt,
+    security::{
+        auth::AuthError,
+        jwt::{AccessClaim, ClaimsMethods},
+        validator::ValidatedJson,
+    },
+    service::{mfa_service, session_service::ClientInfo},
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+
+/// Starts a TOTP enrollment, returning the secret and the `otpauth://` URI to scan.
+pub async fn enroll_totp_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} enroll totp", api_version);
+
+    let user = current_user(&access_claim, &state).await?;
+    let enrollment = mfa_service::start_totp_enrollment(&user, &state).await?;
+
+    Ok(Json(enrollment))
+}
+
+/// Enables TOTP with a code from the enrolled app, returning the recovery codes once.
+pub async fn confirm_totp_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<MfaCodeDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} confirm totp", api_version);
+
+    let user = current_user(&access_claim, &state).await?;
+    let recovery_codes = mfa_service::confirm_totp_enrollment(&user, &body.code, &state).await?;
+
+    Ok(Json(recovery_codes))
+}
+
+pub async fn disable_totp_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<MfaCodeDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} disable totp", api_version);
+
+    let user = current_user(&access_claim, &state).await?;
+    mfa_service::disable_totp(&user, &body.code, client.ip, &state).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn regenerate_recovery_codes_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<MfaCodeDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} regenerate recovery codes", api_version);
+
+    let user = current_user(&access_claim, &state).await?;
+    let recovery_codes = mfa_service::regenerate_recovery_codes(&user, &body.code, client.ip, &state).await?;
+
+    Ok(Json(recovery_codes))
+}
+
+async fn current_user(access_claim: &AccessClaim, state: &SharedState) -> Result<User, ApiError> {
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id).await?;
+    Ok(user)
+}
@@ -1,4 +1,11 @@
 pub mod root_handlers;
 pub mod error_handlers;
 pub mod auth_handlers;
-pub mod user_handlers;
\ No newline at end of file
+pub mod user_handlers;
+pub mod mfa_handlers;
+pub mod api_key_handlers;
+pub mod session_handlers;
+pub mod oauth_handlers;
+pub mod admin_handlers;
+pub mod well_known_handlers;
+pub mod webauthn_handlers;
\ No newline at end of file
@@ -0,0 +1,223 @@
+use axum::{
+    Form, Json,
+    extract::{Query, RawQuery, State, rejection::{FormRejection, QueryRejection}},
+    http::{StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::{Basic, Bearer}},
+};
+use crate::api::{
+    ApiError, ApiVersion,
+    dto::oauth_dto::{
+        AuthorizeDto, AuthorizeResponseDto, DeviceApprovalDto, DeviceCodeRequestDto, IntrospectionRequestDto,
+        TokenRequestDto, UserInfoDto,
+    },
+    extractor::RequireSelf,
+};
+use crate::application::{
+    security::{
+        auth::AuthError,
+        jwt::{AccessClaim, ClaimsMethods},
+        oauth::OAuthError,
+        policy::{self, Resource},
+        validator::ValidatedJson,
+    },
+    repository::user_repository::UserRepositoryExt,
+    service::{api_key_service, oauth_service},
+    state::SharedState,
+};
+use crate::domain::entities::oauth_client::{
+    GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS, GRANT_DEVICE_CODE, GRANT_REFRESH_TOKEN, OAuthClient,
+};
+
+/// Entry point of the authorization code flow, sends the user agent to the consent page of the front-end.
+///
+/// Errors about the request itself are redirected back to the client once its redirect URI is trusted.
+pub async fn authorize_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    RawQuery(raw_query): RawQuery,
+    query: Result<Query<AuthorizeDto>, QueryRejection>,
+) -> Result<Response, OAuthError> {
+    tracing::trace!("api version: {} oauth authorize", api_version);
+
+    let Query(query) = query.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = oauth_service::find_client_for_redirect(&query.client_id, &query.redirect_uri, &state).await?;
+
+    if let Err(e) = oauth_service::validate_authorization_request(&client, &query.request()) {
+        let description = e.to_string();
+        let mut params = vec![("error", e.code()), ("error_description", description.as_str())];
+        if let Some(authorization_state) = &query.state {
+            params.push(("state", authorization_state));
+        }
+        return Ok(Redirect::to(&oauth_service::redirect_uri_with(&query.redirect_uri, &params)).into_response())
+    }
+
+    let consent_uri = format!("{}/oauth/authorize?{}", state.config.app_base_url, raw_query.unwrap_or_default());
+    Ok(Redirect::to(&consent_uri).into_response())
+}
+
+/// Called by the consent page once the signed-in user approves the client, issues the authorization code.
+pub async fn approve_authorization_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<AuthorizeDto>,
+) -> Result<impl IntoResponse, OAuthError> {
+    tracing::trace!("api version: {} oauth approve authorization", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let redirect_uri = oauth_service::authorize(&body.request(), user_id, body.state.as_deref(), &state).await?;
+
+    Ok(Json(AuthorizeResponseDto { redirect_uri }))
+}
+
+pub async fn token_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    form: Result<Form<TokenRequestDto>, FormRejection>,
+) -> Result<impl IntoResponse, OAuthError> {
+    tracing::trace!("api version: {} oauth token", api_version);
+
+    let Form(body) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = authenticate_client(basic, &body.client_id, &body.client_secret, &state).await?;
+
+    let token = match body.grant_type.as_str() {
+        GRANT_AUTHORIZATION_CODE => oauth_service::exchange_authorization_code(
+            &client,
+            required(&body.code, "code")?,
+            required(&body.redirect_uri, "redirect_uri")?,
+            required(&body.code_verifier, "code_verifier")?,
+            &state,
+        ).await?,
+        GRANT_REFRESH_TOKEN => oauth_service::refresh(
+            &client,
+            required(&body.refresh_token, "refresh_token")?,
+            &state,
+        ).await?,
+        GRANT_CLIENT_CREDENTIALS => oauth_service::client_credentials(&client, body.scope.as_deref(), &state).await?,
+        GRANT_DEVICE_CODE => oauth_service::exchange_device_code(
+            &client,
+            required(&body.device_code, "device_code")?,
+            &state,
+        ).await?,
+        _ => return Err(OAuthError::UnsupportedGrantType),
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store"), (header::PRAGMA, "no-cache")], Json(token)))
+}
+
+/// Device authorization endpoint of RFC 8628, for clients without a browser such as CLI tools.
+pub async fn device_code_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    form: Result<Form<DeviceCodeRequestDto>, FormRejection>,
+) -> Result<impl IntoResponse, OAuthError> {
+    tracing::trace!("api version: {} oauth device code", api_version);
+
+    let Form(body) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = authenticate_client(basic, &body.client_id, &body.client_secret, &state).await?;
+    let device_code = oauth_service::create_device_code(&client, body.scope.as_deref(), &state).await?;
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(device_code)))
+}
+
+/// Called by the verification page once the signed-in user approves or denies the user code shown by the device.
+pub async fn device_approval_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<DeviceApprovalDto>,
+) -> Result<impl IntoResponse, OAuthError> {
+    tracing::trace!("api version: {} oauth device approval", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    oauth_service::decide_device_authorization(&body.user_code, user_id, body.approved, &state).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Token introspection of RFC 7662, for confidential clients and API keys granted `tokens:introspect`.
+pub async fn introspect_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    form: Result<Form<IntrospectionRequestDto>, FormRejection>,
+) -> Result<impl IntoResponse, OAuthError> {
+    tracing::trace!("api version: {} oauth introspect", api_version);
+
+    let Form(body) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+
+    match bearer {
+        Some(TypedHeader(Authorization(bearer))) => {
+            if !api_key_service::is_api_key(bearer.token()) {
+                return Err(OAuthError::InvalidClient)
+            }
+            let claims = api_key_service::authenticate(bearer.token(), &state)
+                .await
+                .map_err(|e| match e {
+                    AuthError::InvalidBearerToken => OAuthError::InvalidClient,
+                    _ => OAuthError::from(e),
+                })?;
+            state.config.policy
+                .authorize(&claims, policy::TOKENS_INTROSPECT, &Resource::any())
+                .map_err(|_| OAuthError::InvalidClient)?;
+        }
+        None => {
+            // Public clients hold no secret, anyone could introspect on their behalf.
+            let client = authenticate_client(basic, &body.client_id, &body.client_secret, &state).await?;
+            if !client.is_confidential() {
+                return Err(OAuthError::InvalidClient)
+            }
+        }
+    }
+
+    let introspection = oauth_service::introspect(&body.token, &state).await?;
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(introspection)))
+}
+
+/// Claims about the user a token was issued to, in the style of the OpenID Connect userinfo endpoint.
+pub async fn userinfo_handler(
+    api_version: ApiVersion,
+    access_claim: AccessClaim,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} oauth userinfo", api_version);
+
+    // Tokens of the client credentials grant have a client ID as subject.
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    state.config.policy.authorize(&access_claim, policy::USERS_READ, &Resource::owned_by(access_claim.get_sub()))?;
+
+    let user = state.get_user_by_id(user_id).await?;
+
+    Ok(Json(UserInfoDto::from(user)))
+}
+
+/// Authenticates the client with either HTTP Basic or the form parameters.
+async fn authenticate_client(
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    client_id: &Option<String>,
+    client_secret: &Option<String>,
+    state: &SharedState,
+) -> Result<OAuthClient, OAuthError> {
+    // RFC 6749 section 2.3.1: a client must not use more than one authentication method.
+    let (client_id, client_secret) = match (basic, client_id) {
+        (Some(_), Some(_)) if client_secret.is_some() => {
+            return Err(OAuthError::InvalidRequest("multiple client authentication methods".to_owned()))
+        }
+        (Some(TypedHeader(Authorization(basic))), _) => (basic.username().to_owned(), Some(basic.password().to_owned())),
+        (None, Some(client_id)) => (client_id.to_owned(), client_secret.to_owned()),
+        (None, None) => return Err(OAuthError::InvalidClient),
+    };
+    oauth_service::authenticate_client(&client_id, client_secret.as_deref(), state).await
+}
+
+fn required<'a>(value: &'a Option<String>, name: &str) -> Result<&'a str, OAuthError> {
+    value.as_deref().ok_or_else(|| OAuthError::InvalidRequest(format!("missing parameter: {}", name)))
+}
@@ -0,0 +1,55 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use crate::api::{ApiError, ApiVersion, dto::user_dto::SessionDto, extractor::RequireSession};
+use crate::application::{
+    security::auth::AuthError,
+    service::session_service,
+    state::SharedState,
+};
+
+pub async fn list_sessions_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} list sessions", api_version);
+
+    let sessions: Vec<SessionDto> = session_service::list_sessions(&access_claim.sub, &state)
+        .await
+        .map_err(AuthError::from)?
+        .into_iter()
+        .map(|session| SessionDto {
+            current: session.id == access_claim.fid,
+            session,
+        })
+        .collect();
+
+    Ok(Json(sessions))
+}
+
+/// Signs out a single session, such as one left open on a lost device.
+pub async fn revoke_session_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+    Path((_, session_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} revoke session", api_version);
+
+    let session = session_service::get_session(&session_id, &state)
+        .await
+        .map_err(AuthError::from)?
+        .filter(|session| session.user_id == access_claim.sub)
+        .ok_or(AuthError::SessionNotFound)?;
+
+    session_service::revoke_session(&session.user_id, &session.id, &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("session {} revoked by {}", session.id, access_claim.sub);
+
+    Ok(StatusCode::NO_CONTENT)
+}
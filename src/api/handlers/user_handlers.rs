@@ -1,36 +1,154 @@
 use axum::{
     Json,
-    response::IntoResponse,
-    extract::State,
+    response::{IntoResponse, Response},
+    extract::{Path, State},
     http::StatusCode,
 };
 use thiserror::Error;
 use uuid::Uuid;
-use crate::api::{ApiError, ApiErrorCode, ApiErrorKind, ApiErrorResponse};
+use crate::api::{ApiError, ApiErrorCode, ApiErrorKind, ApiErrorResponse, dto::user_dto::ChangePasswordDto, extractor::{RequirePermission, RequireSelf}};
 use crate::application::{
-    security::jwt::{AccessClaim, ClaimsMethods},
+    security::{
+        auth::{self, AuthError},
+        jwt::{AccessClaim, ClaimsMethods},
+        policy::{self, Resource, UsersDeactivate, UsersRead},
+        validator::ValidatedJson,
+    },
+    service::{login_attempt_service, session_service::{self, ClientInfo}, token_service},
     state::SharedState,
 };
-use crate::application::repository::user_repository::UserRepositoryExt;
+use crate::application::repository::user_repository::{self, UserRepositoryExt};
 
 pub async fn me_handler(
     access_claim: AccessClaim,
     State(state): State<SharedState>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let user_id = access_claim.get_sub().parse().unwrap();
+    // Tokens of the client credentials grant have a client ID as subject.
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
     let user = state.get_user_by_id(user_id)
         .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => {
-                let user_error = UserError::UserNotFound(user_id);
-                (user_error.status_code(), ApiErrorResponse::from(user_error)).into()
-            },
-            _ => ApiError::from(e),
-        })?;
+        .map_err(|e| user_error(e, user_id))?;
 
     Ok(Json(user))
 }
 
+/// Changes the password of the signed-in user and signs out every other session.
+///
+/// When the current session is kept, a fresh token pair is returned to replace the revoked one.
+pub async fn change_password_handler(
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<ChangePasswordDto>,
+) -> Result<Response, ApiError> {
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| user_error(e, user_id))?;
+
+    // Throttled like a login, a stolen access token must not turn this into a password oracle.
+    let identifier = user.id.to_string();
+    login_attempt_service::check_lockout(&identifier, client.ip, &state).await?;
+    let delay = login_attempt_service::failure_delay(&identifier, &state)
+        .await
+        .map_err(AuthError::from)?;
+    if !delay.is_zero() {
+        tracing::debug!("delaying password change by {:?}", delay);
+        tokio::time::sleep(delay).await;
+    }
+
+    let password_matches = match state.hasher.compare(&body.current_password, &user.password_hash).await {
+        Ok(password_matches) => password_matches,
+        Err(e @ AuthError::HashingOverloaded(_)) => return Err(e.into()),
+        Err(_) => false,
+    };
+    if !password_matches {
+        login_attempt_service::record_failure(&identifier, client.ip, &state)
+            .await
+            .map_err(AuthError::from)?;
+        return Err(AuthError::WrongCredentials.into())
+    }
+    login_attempt_service::record_success(&identifier, &state)
+        .await
+        .map_err(AuthError::from)?;
+
+    let password_hash = state.hasher.hash(body.new_password, &state.config.password_hash_policy).await?;
+    let user = state.update_password_hash(user_id, &password_hash).await?;
+    tracing::info!("password of user {} changed", user.id);
+
+    token_service::revoke_refresh_family(&access_claim.fid, &state)
+        .await
+        .map_err(AuthError::from)?;
+    session_service::remove_session(&access_claim.sub, &access_claim.fid, &state)
+        .await
+        .map_err(AuthError::from)?;
+
+    // Revokes every token issued up to now, the replacement pair is issued after the watermark.
+    token_service::revoke_user_tokens(&user.id.to_string(), &state)
+        .await
+        .map_err(AuthError::from)?;
+
+    if !body.keep_current_session.unwrap_or(true) {
+        return Ok(StatusCode::NO_CONTENT.into_response())
+    }
+
+    let token = auth::issue_token(&user, &access_claim.aud, &client, &state).await?;
+
+    Ok(Json(token).into_response())
+}
+
+pub async fn list_users_handler(
+    RequirePermission(_, _): RequirePermission<UsersRead>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let users = user_repository::list(&state).await?;
+
+    Ok(Json(users))
+}
+
+pub async fn get_user_handler(
+    access_claim: AccessClaim,
+    State(state): State<SharedState>,
+    Path((_, user_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let owner_id = user_id.to_string();
+    state.config.policy.authorize(&access_claim, policy::USERS_READ, &Resource::owned_by(&owner_id))?;
+
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| user_error(e, user_id))?;
+
+    Ok(Json(user))
+}
+
+pub async fn deactivate_user_handler(
+    RequirePermission(access_claim, _): RequirePermission<UsersDeactivate>,
+    State(state): State<SharedState>,
+    Path((_, user_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user = state.set_user_active(user_id, false)
+        .await
+        .map_err(|e| user_error(e, user_id))?;
+
+    // Deactivated users must not keep using the tokens they already hold.
+    token_service::revoke_user_tokens(&user_id.to_string(), &state)
+        .await
+        .map_err(AuthError::from)?;
+    tracing::info!("user {} deactivated by {}", user_id, access_claim.sub);
+
+    Ok(Json(user))
+}
+
+fn user_error(e: sqlx::Error, user_id: Uuid) -> ApiError {
+    match e {
+        sqlx::Error::RowNotFound => {
+            let user_error = UserError::UserNotFound(user_id);
+            (user_error.status_code(), ApiErrorResponse::from(user_error)).into()
+        },
+        _ => ApiError::from(e),
+    }
+}
+
 #[derive(Debug, Error)]
 enum UserError {
     #[error("user not found: {0}")]
@@ -44,7 +162,7 @@ impl From<UserError> for ApiErrorResponse {
             UserError::UserNotFound(user_id) => Self::new(&message)
                 .code(ApiErrorCode::UserNotFound)
                 .kind(ApiErrorKind::ResourceNotFound)
-                .description(&format!("user with the ID '{}' does not exists", user_id))
+                .description(format!("user with the ID '{}' does not exists", user_id))
                 .detail(serde_json::json!({"user_id": user_id}))
                 .reason("must be an existing user in the database")
                 .instance(&format!("/api/v1/users/{}", user_id))
@@ -59,4 +177,4 @@ impl UserError {
             Self::UserNotFound(_) => StatusCode::NOT_FOUND,
         }
     }
-}
\ No newline at end of file
+}
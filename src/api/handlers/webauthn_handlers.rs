@@ -0,0 +1,132 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use uuid::Uuid;
+use crate::api::{
+    ApiError, ApiVersion,
+    dto::{
+        auth_dto::{WebAuthnLoginDto, WebAuthnOptionsDto},
+        user_dto::RegisterWebAuthnCredentialDto,
+    },
+    extractor::{RequireSelf, RequireSession},
+};
+use crate::application::{
+    repository::{user_repository::UserRepositoryExt, webauthn_repository::WebAuthnRepositoryExt},
+    security::{
+        auth::{self, AuthError},
+        jwt::ClaimsMethods,
+        validator::ValidatedJson,
+    },
+    service::{
+        login_attempt_service,
+        session_service::ClientInfo,
+        webauthn_service::{self, AssertionResponse, AttestationResponse},
+    },
+    state::SharedState,
+};
+
+/// Starts the registration of a passkey, returning the options of `navigator.credentials.create()`.
+pub async fn registration_options_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn registration options", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id).await?;
+    let options = webauthn_service::start_registration(&user, &state).await?;
+
+    Ok(Json(options))
+}
+
+pub async fn register_credential_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<RegisterWebAuthnCredentialDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn register credential", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id).await?;
+    let response = AttestationResponse {
+        client_data_json: &body.credential.response.client_data_json,
+        attestation_object: &body.credential.response.attestation_object,
+    };
+    let credential = webauthn_service::finish_registration(&user, &body.name, &response, &state).await?;
+
+    Ok((StatusCode::CREATED, Json(credential)))
+}
+
+pub async fn list_credentials_handler(
+    api_version: ApiVersion,
+    RequireSession(access_claim): RequireSession,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn list credentials", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    let credentials = state.list_webauthn_credentials(user_id).await?;
+
+    Ok(Json(credentials))
+}
+
+pub async fn delete_credential_handler(
+    api_version: ApiVersion,
+    RequireSelf(access_claim): RequireSelf,
+    State(state): State<SharedState>,
+    Path((_, credential_id)): Path<(String, Uuid)>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn delete credential", api_version);
+
+    let user_id = access_claim.get_sub().parse().map_err(|_| AuthError::InvalidToken)?;
+    state.delete_webauthn_credential(user_id, credential_id).await?;
+    tracing::info!("webauthn credential {} deleted by {}", credential_id, user_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts a passkey login, returning the options of `navigator.credentials.get()`.
+#[tracing::instrument(level = tracing::Level::TRACE, name = "webauthn_options", skip_all)]
+pub async fn authentication_options_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    ValidatedJson(body): ValidatedJson<WebAuthnOptionsDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn authentication options", api_version);
+
+    let options = webauthn_service::start_authentication(body.identifier.as_deref(), &state).await?;
+
+    Ok(Json(options))
+}
+
+/// Passwordless login, answers with the same token pair as a password login.
+///
+/// A passkey verifying the user already is a second factor, so no TOTP challenge follows.
+#[tracing::instrument(level = tracing::Level::TRACE, name = "webauthn_login", skip_all)]
+pub async fn login_webauthn_handler(
+    api_version: ApiVersion,
+    State(state): State<SharedState>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<WebAuthnLoginDto>,
+) -> Result<impl IntoResponse, ApiError> {
+    tracing::trace!("api version: {} webauthn login", api_version);
+
+    let response = AssertionResponse {
+        credential_id: &body.id,
+        client_data_json: &body.response.client_data_json,
+        authenticator_data: &body.response.authenticator_data,
+        signature: &body.response.signature,
+        user_handle: body.response.user_handle.as_deref(),
+    };
+    let user = webauthn_service::finish_authentication(&response, &state).await?;
+    login_attempt_service::check_user_lockout(&user, client.ip, &state).await?;
+    let audience = auth::token_audience(body.audience.as_deref(), &state.config)?;
+    let token = auth::issue_token(&user, &audience, &client, &state).await?;
+
+    Ok(Json(token))
+}
@@ -0,0 +1,7 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use crate::api::ApiError;
+use crate::application::state::SharedState;
+
+pub async fn jwks_handler(State(state): State<SharedState>) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(state.config.jwt_keys.jwks()))
+}
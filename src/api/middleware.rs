@@ -4,6 +4,8 @@ use axum::{
     body::Body,
     middleware::Next
 };
+use crate::api::extractor::{RequireAudience, RequireRole};
+use crate::application::security::{jwt::RequiredAudience, role::RequiredRole};
 
 #[tracing::instrument(level = tracing::Level::TRACE, name = "axum", skip_all, fields(method=request.method().to_string(), uri=request.uri().to_string()))]
 pub async fn logging_middleware(request: Request<Body>, next: Next) -> Response {
@@ -13,4 +15,25 @@ pub async fn logging_middleware(request: Request<Body>, next: Next) -> Response
         request.uri()
     );
     next.run(request).await
-}
\ No newline at end of file
+}
+
+/// Route group guard, use with `middleware::from_fn_with_state(state, require_role::<Admin>)`.
+pub async fn require_role<R: RequiredRole>(
+    RequireRole(claims, _): RequireRole<R>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    // Handlers extracting `AccessClaim` reuse the claims instead of decoding the token again.
+    request.extensions_mut().insert(claims);
+    next.run(request).await
+}
+
+/// Route group guard, use with `middleware::from_fn_with_state(state, require_audience::<AdminAudience>)`.
+pub async fn require_audience<A: RequiredAudience>(
+    RequireAudience(claims, _): RequireAudience<A>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    request.extensions_mut().insert(claims);
+    next.run(request).await
+}
@@ -0,0 +1,27 @@
+use axum::{
+    Router,
+    routing::{delete, get, post}
+};
+use crate::api::handlers::admin_handlers::{
+    list_revocations_handler, clear_revocations_handler,
+    revoke_global_handler, unrevoke_global_handler,
+    revoke_user_handler, unrevoke_user_handler,
+    revoke_token_handler, unrevoke_token_handler,
+    unlock_identifier_handler, unlock_ip_handler,
+    list_oauth_clients_handler, create_oauth_client_handler, delete_oauth_client_handler,
+    impersonate_user_handler,
+};
+use crate::application::state::SharedState;
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/revocations", get(list_revocations_handler).delete(clear_revocations_handler))
+        .route("/revocations/global", post(revoke_global_handler).delete(unrevoke_global_handler))
+        .route("/revocations/users/{user_id}", post(revoke_user_handler).delete(unrevoke_user_handler))
+        .route("/revocations/tokens/{jti}", post(revoke_token_handler).delete(unrevoke_token_handler))
+        .route("/lockouts/identifiers/{identifier}", delete(unlock_identifier_handler))
+        .route("/lockouts/ips/{ip}", delete(unlock_ip_handler))
+        .route("/oauth/clients", get(list_oauth_clients_handler).post(create_oauth_client_handler))
+        .route("/oauth/clients/{client_id}", delete(delete_oauth_client_handler))
+        .route("/users/{user_id}/impersonate", post(impersonate_user_handler))
+}
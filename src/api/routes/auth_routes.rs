@@ -3,11 +3,30 @@ use axum::{
     routing::{post}
 };
 use crate::api::handlers::{
-    auth_handlers::{login_handler}
+    auth_handlers::{
+        login_handler, register_handler, refresh_handler, logout_handler, logout_all_handler,
+        verify_email_handler, resend_verification_email_handler,
+        forgot_password_handler, reset_password_handler, verify_mfa_handler,
+        request_magic_link_handler, consume_magic_link_handler,
+    },
+    webauthn_handlers::{authentication_options_handler, login_webauthn_handler},
 };
 use crate::application::state::SharedState;
 
 pub fn routes() -> Router<SharedState> {
     Router::new()
         .route("/login", post(login_handler))
+        .route("/mfa/verify", post(verify_mfa_handler))
+        .route("/magic-link", post(request_magic_link_handler))
+        .route("/magic-link/consume", post(consume_magic_link_handler))
+        .route("/webauthn/options", post(authentication_options_handler))
+        .route("/webauthn/login", post(login_webauthn_handler))
+        .route("/register", post(register_handler))
+        .route("/verify-email", post(verify_email_handler))
+        .route("/verify-email/resend", post(resend_verification_email_handler))
+        .route("/password/forgot", post(forgot_password_handler))
+        .route("/password/reset", post(reset_password_handler))
+        .route("/refresh", post(refresh_handler))
+        .route("/logout", post(logout_handler))
+        .route("/logout-all", post(logout_all_handler))
 }
\ No newline at end of file
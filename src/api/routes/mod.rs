@@ -1,2 +1,4 @@
 pub mod auth_routes;
-pub mod user_routes;
\ No newline at end of file
+pub mod user_routes;
+pub mod admin_routes;
+pub mod oauth_routes;
\ No newline at end of file
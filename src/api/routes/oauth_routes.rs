@@ -0,0 +1,19 @@
+use axum::{
+    Router,
+    routing::{get, post}
+};
+use crate::api::handlers::oauth_handlers::{
+    authorize_handler, approve_authorization_handler, token_handler, device_code_handler, device_approval_handler,
+    introspect_handler, userinfo_handler,
+};
+use crate::application::state::SharedState;
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/authorize", get(authorize_handler).post(approve_authorization_handler))
+        .route("/token", post(token_handler))
+        .route("/device/code", post(device_code_handler))
+        .route("/device/approve", post(device_approval_handler))
+        .route("/introspect", post(introspect_handler))
+        .route("/userinfo", get(userinfo_handler).post(userinfo_handler))
+}
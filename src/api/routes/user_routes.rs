@@ -1,8 +1,34 @@
-use axum::{Router, routing::get};
+use axum::{Router, routing::{delete, get, post, put}};
+use crate::api::handlers::api_key_handlers::{
+    list_api_keys_handler, create_api_key_handler, revoke_api_key_handler,
+};
+use crate::api::handlers::session_handlers::{list_sessions_handler, revoke_session_handler};
+use crate::api::handlers::mfa_handlers::{
+    enroll_totp_handler, confirm_totp_handler, disable_totp_handler, regenerate_recovery_codes_handler,
+};
+use crate::api::handlers::webauthn_handlers::{
+    registration_options_handler, register_credential_handler, list_credentials_handler, delete_credential_handler,
+};
 use crate::application::state::SharedState;
-use crate::api::handlers::user_handlers::{me_handler};
+use crate::api::handlers::user_handlers::{
+    me_handler, change_password_handler, list_users_handler, get_user_handler, deactivate_user_handler,
+};
 
 pub fn routes() -> Router<SharedState> {
     Router::new()
+        .route("/", get(list_users_handler))
         .route("/me", get(me_handler))
-}
\ No newline at end of file
+        .route("/me/password", put(change_password_handler))
+        .route("/me/sessions", get(list_sessions_handler))
+        .route("/me/sessions/{session_id}", delete(revoke_session_handler))
+        .route("/me/tokens", get(list_api_keys_handler).post(create_api_key_handler))
+        .route("/me/tokens/{token_id}", delete(revoke_api_key_handler))
+        .route("/me/mfa/totp", post(enroll_totp_handler).delete(disable_totp_handler))
+        .route("/me/mfa/totp/confirm", post(confirm_totp_handler))
+        .route("/me/mfa/recovery-codes", post(regenerate_recovery_codes_handler))
+        .route("/me/webauthn/registration/options", post(registration_options_handler))
+        .route("/me/webauthn/credentials", get(list_credentials_handler).post(register_credential_handler))
+        .route("/me/webauthn/credentials/{credential_id}", delete(delete_credential_handler))
+        .route("/{user_id}", get(get_user_handler))
+        .route("/{user_id}/deactivate", post(deactivate_user_handler))
+}
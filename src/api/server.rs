@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 use axum::{
     Router,
@@ -8,9 +9,10 @@ use crate::api::{
     handlers::{
         root_handlers::{index, health_handler},
         error_handlers::error_404_handler,
+        well_known_handlers::jwks_handler,
     },
-    middleware::{logging_middleware},
-    routes::{auth_routes, user_routes},
+    middleware::{logging_middleware, require_audience},
+    routes::{auth_routes, user_routes, admin_routes, oauth_routes},
 };
 use tokio::{
     net::TcpListener,
@@ -18,6 +20,7 @@ use tokio::{
 };
 use tower_http::cors::{CorsLayer, Any};
 use crate::application::{
+    security::jwt::AdminAudience,
     state::{SharedState},
 };
 
@@ -27,9 +30,14 @@ pub async fn start(state: SharedState) {
 
     let router = Router::new()
         .route("/", get(index))
+        .route("/.well-known/jwks.json", get(jwks_handler))
         .route("/{version}/health", get(health_handler))
         .nest("/{version}/auth", auth_routes::routes())
         .nest("/{version}/users", user_routes::routes())
+        .nest("/{version}/oauth", oauth_routes::routes())
+        // Each admin handler requires its own permission, the group only requires an admin token.
+        .nest("/{version}/admin", admin_routes::routes()
+            .route_layer(middleware::from_fn_with_state(Arc::clone(&state), require_audience::<AdminAudience>)))
         .fallback(error_404_handler)
         .with_state(Arc::clone(&state))
         .layer(middleware::from_fn(logging_middleware))
@@ -39,7 +47,7 @@ pub async fn start(state: SharedState) {
     let listener = TcpListener::bind(&addr).await.unwrap();
     tracing::info!("listening on {}", addr);
 
-    axum::serve(listener, router)
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
@@ -78,6 +78,6 @@ impl From<ApiVersionError> for ApiError {
 pub fn parse_version(version: &str) -> Result<ApiVersion, ApiError> {
     version.parse().map_or_else(
         |_| Err(ApiVersionError::InvalidVersion(version.to_owned()).into()),
-        |v| Ok(v)
+        Ok
     )
 }
\ No newline at end of file
@@ -3,9 +3,10 @@ use tokio::sync::Mutex;
 use crate::api::server;
 use crate::application::{
     config,
+    security::password::HashingPool,
     state::AppState,
 };
-use crate::infra::{cache, database};
+use crate::infra::{cache, database, mail};
 
 pub async fn run() {
     let config = config::load();
@@ -16,10 +17,16 @@ pub async fn run() {
 
     let cache = cache::load(&config).await;
 
+    let mailer = mail::load(&config);
+
+    let hasher = HashingPool::new(&config);
+
     let shared_state = Arc::new(AppState {
         config,
         db_pool,
         cache: Mutex::new(cache),
+        mailer,
+        hasher,
     });
 
     server::start(shared_state).await
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+use jsonwebtoken::Algorithm;
+use thiserror::Error;
+use crate::application::config;
+use crate::application::security::keyring::{KeyEntry, KeyRingError, KeyRingManifest, KeyStatus};
+
+const USAGE: &str = r#"usage:
+    keys list                              list the keys of the key ring
+    keys add <kid> <algorithm> <source>    stage a verification-only key, <source> is a PEM file
+                                           or, for HMAC algorithms, an environment variable name
+    keys promote <kid>                     sign with <kid>, the previous signing key is retired
+                                           once every instance restarted and every token it
+                                           signed has expired
+    keys prune                             remove retired keys
+
+Rotation without downtime:
+    1. keys add, then restart every instance so they all accept the new key
+    2. keys promote, then restart every instance so they sign with the new key, within
+       JWT_KEY_RESTART_WINDOW_SECONDS (1 hour by default)
+    3. keys prune once the previous key is retired
+
+The key ring manifest is read from JWT_KEYRING_PATH."#;
+
+#[derive(Debug, Error)]
+enum CliError {
+    #[error("environment variable is not set or invalid: {0}")]
+    InvalidEnv(&'static str),
+    #[error("unknown algorithm: {0}")]
+    InvalidAlgorithm(String),
+    #[error(transparent)]
+    KeyRingError(#[from] KeyRingError),
+}
+
+/// Runs an operator command and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    config::load_env();
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["keys", "list"] => list_keys(),
+        ["keys", "add", kid, algorithm, source] => add_key(kid, algorithm, source),
+        ["keys", "promote", kid] => promote_key(kid),
+        ["keys", "prune"] => prune_keys(),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!("{}", e);
+            1
+        }
+    }
+}
+
+fn list_keys() -> Result<(), CliError> {
+    let (_, manifest) = load_manifest()?;
+    for key in manifest.keys {
+        let status = match key.status {
+            KeyStatus::Signing => "signing",
+            KeyStatus::Verification => "verification",
+        };
+        let retire_at = key.retire_at
+            .and_then(|retire_at| chrono::DateTime::from_timestamp(retire_at, 0))
+            .map_or_else(String::new, |retire_at| format!(" (retires at {})", retire_at));
+        println!("{}\t{:?}\t{}{}", key.kid, key.algorithm, status, retire_at);
+    }
+    Ok(())
+}
+
+fn add_key(kid: &str, algorithm: &str, source: &str) -> Result<(), CliError> {
+    let algorithm: Algorithm = algorithm.parse()
+        .map_err(|_| CliError::InvalidAlgorithm(algorithm.to_owned()))?;
+    let is_hmac = matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512);
+
+    let (path, mut manifest) = load_manifest()?;
+    manifest.add(KeyEntry {
+        kid: kid.to_owned(),
+        algorithm,
+        private_key_path: (!is_hmac).then(|| PathBuf::from(source)),
+        secret_env: is_hmac.then(|| source.to_owned()),
+        status: KeyStatus::Verification,
+        retire_at: None,
+    })?;
+
+    save_manifest(&path, &manifest)?;
+    println!("key {} added", kid);
+    Ok(())
+}
+
+fn promote_key(kid: &str) -> Result<(), CliError> {
+    let config = config::load();
+    // Instances sign with the previous key until they restart, its last tokens are valid for a lifetime after that.
+    let retire_after = config.jwt_key_restart_window_seconds + config.max_token_lifetime();
+
+    let (path, mut manifest) = load_manifest()?;
+    manifest.promote(kid, retire_after)?;
+
+    save_manifest(&path, &manifest)?;
+    println!("key {} promoted", kid);
+    Ok(())
+}
+
+fn prune_keys() -> Result<(), CliError> {
+    let (path, mut manifest) = load_manifest()?;
+    let pruned = manifest.prune(chrono::Utc::now().timestamp());
+
+    save_manifest(&path, &manifest)?;
+    for kid in pruned {
+        println!("key {} removed", kid);
+    }
+    Ok(())
+}
+
+fn load_manifest() -> Result<(PathBuf, KeyRingManifest), CliError> {
+    let path = std::env::var("JWT_KEYRING_PATH")
+        .map(PathBuf::from)
+        .map_err(|_| CliError::InvalidEnv("JWT_KEYRING_PATH"))?;
+    let manifest = KeyRingManifest::load(&path)?;
+    Ok((path, manifest))
+}
+
+fn save_manifest(path: &Path, manifest: &KeyRingManifest) -> Result<(), CliError> {
+    // Make sure every key can be loaded before instances pick the manifest up.
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    manifest.key_ring(base_dir)?;
+    manifest.save(path)?;
+    Ok(())
+}
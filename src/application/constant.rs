@@ -1,3 +1,24 @@
 pub const JWT_REDIS_REVOKE_GLOBAL_BEFORE: &str = "jwt.redis.revoke.global.before";
 pub const JWT_REDIS_REVOKE_USER_BEFORE_KEY: &str = "jwt.revoke.user.before";
-pub const JWT_REDIS_REVOKED_TOKENS_KEY: &str = "jwt.revoked.tokens";
\ No newline at end of file
+pub const JWT_REDIS_REVOKED_TOKENS_KEY: &str = "jwt.revoked.tokens";
+pub const JWT_REDIS_REVOKED_TOKENS_EXP_KEY: &str = "jwt.revoked.tokens.exp";
+pub const JWT_REDIS_REFRESH_FAMILY_KEY: &str = "jwt.refresh.family";
+pub const JWT_REDIS_REVOKED_FAMILY_KEY: &str = "jwt.revoked.family";
+pub const PASSWORD_RESET_REDIS_KEY: &str = "password.reset";
+pub const PASSWORD_RESET_REDIS_USER_KEY: &str = "password.reset.user";
+pub const LOGIN_FAILURES_IDENTIFIER_KEY: &str = "login.failures.identifier";
+pub const LOGIN_FAILURES_IP_KEY: &str = "login.failures.ip";
+pub const LOGIN_LOCKOUT_IDENTIFIER_KEY: &str = "login.lockout.identifier";
+pub const LOGIN_LOCKOUT_IP_KEY: &str = "login.lockout.ip";
+pub const MAGIC_LINK_USED_KEY: &str = "magic.link.used";
+pub const MFA_PENDING_USED_KEY: &str = "mfa.pending.used";
+pub const MFA_TOTP_USED_KEY: &str = "mfa.totp.used";
+pub const API_KEY_PREFIX: &str = "ark_";
+pub const SESSION_REDIS_KEY: &str = "session";
+pub const SESSION_REDIS_USER_KEY: &str = "session.user";
+pub const OAUTH_CODE_REDIS_KEY: &str = "oauth.code";
+pub const OAUTH_DEVICE_CODE_REDIS_KEY: &str = "oauth.device";
+pub const OAUTH_DEVICE_USER_CODE_REDIS_KEY: &str = "oauth.device.user";
+pub const OAUTH_DEVICE_POLL_REDIS_KEY: &str = "oauth.device.poll";
+pub const WEBAUTHN_REGISTRATION_REDIS_KEY: &str = "webauthn.registration";
+pub const WEBAUTHN_AUTHENTICATION_REDIS_KEY: &str = "webauthn.authentication";
\ No newline at end of file
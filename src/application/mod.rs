@@ -1,4 +1,5 @@
 pub mod app;
+pub mod cli;
 pub mod config;
 pub mod state;
 pub mod repository;
@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+use crate::application::{
+    repository::RepositoryResult,
+    state::AppState,
+};
+use crate::domain::entities::api_key::{ApiKey, NewApiKey};
+
+#[async_trait]
+pub trait ApiKeyRepositoryExt {
+    async fn create_api_key(&self, new_api_key: &NewApiKey) -> RepositoryResult<ApiKey>;
+    async fn list_api_keys(&self, user_id: Uuid) -> RepositoryResult<Vec<ApiKey>>;
+    async fn get_active_api_key_by_hash(&self, key_hash: &str) -> RepositoryResult<Option<ApiKey>>;
+    async fn revoke_api_key(&self, user_id: Uuid, api_key_id: Uuid) -> RepositoryResult<ApiKey>;
+    async fn touch_api_key(&self, api_key_id: Uuid) -> RepositoryResult<()>;
+}
+
+#[async_trait]
+impl ApiKeyRepositoryExt for AppState {
+    async fn create_api_key(&self, new_api_key: &NewApiKey) -> RepositoryResult<ApiKey> {
+        let query = r#"
+            INSERT INTO api_keys (user_id, name, prefix, key_hash, scopes, expires_at, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+            RETURNING *
+        "#;
+
+        let api_key = sqlx::query_as::<_, ApiKey>(query)
+            .bind(new_api_key.user_id)
+            .bind(&new_api_key.name)
+            .bind(&new_api_key.prefix)
+            .bind(&new_api_key.key_hash)
+            .bind(&new_api_key.scopes)
+            .bind(new_api_key.expires_at)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(api_key)
+    }
+
+    async fn list_api_keys(&self, user_id: Uuid) -> RepositoryResult<Vec<ApiKey>> {
+        let query = r#"
+            SELECT * FROM api_keys WHERE user_id = $1 ORDER BY created_at DESC
+        "#;
+
+        let api_keys = sqlx::query_as::<_, ApiKey>(query)
+            .bind(user_id)
+            .fetch_all(&*self.db_pool)
+            .await?;
+
+        Ok(api_keys)
+    }
+
+    async fn get_active_api_key_by_hash(&self, key_hash: &str) -> RepositoryResult<Option<ApiKey>> {
+        let query = r#"
+            SELECT * FROM api_keys
+            WHERE key_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now())
+        "#;
+
+        let api_key = sqlx::query_as::<_, ApiKey>(query)
+            .bind(key_hash)
+            .fetch_optional(&*self.db_pool)
+            .await?;
+
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(&self, user_id: Uuid, api_key_id: Uuid) -> RepositoryResult<ApiKey> {
+        let query = r#"
+            UPDATE api_keys SET revoked_at = COALESCE(revoked_at, now())
+            WHERE id = $1 AND user_id = $2 RETURNING *
+        "#;
+
+        let api_key = sqlx::query_as::<_, ApiKey>(query)
+            .bind(api_key_id)
+            .bind(user_id)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(api_key)
+    }
+
+    async fn touch_api_key(&self, api_key_id: Uuid) -> RepositoryResult<()> {
+        // Keys used in a loop must not turn every request into a write.
+        let query = r#"
+            UPDATE api_keys SET last_used_at = now()
+            WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < now() - INTERVAL '1 minute')
+        "#;
+
+        sqlx::query(query)
+            .bind(api_key_id)
+            .execute(&*self.db_pool)
+            .await?;
+
+        Ok(())
+    }
+}
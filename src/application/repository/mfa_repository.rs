@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+use crate::application::{
+    repository::RepositoryResult,
+    state::AppState,
+};
+use crate::domain::entities::{recovery_code::RecoveryCode, user::User};
+
+#[async_trait]
+pub trait MfaRepositoryExt {
+    async fn set_pending_totp_secret(&self, user_id: Uuid, secret: &str) -> RepositoryResult<User>;
+    async fn enable_totp(&self, user_id: Uuid, code_hashes: &[String]) -> RepositoryResult<User>;
+    async fn disable_totp(&self, user_id: Uuid) -> RepositoryResult<User>;
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> RepositoryResult<()>;
+    async fn list_unused_recovery_codes(&self, user_id: Uuid) -> RepositoryResult<Vec<RecoveryCode>>;
+    async fn use_recovery_code(&self, code_id: Uuid) -> RepositoryResult<bool>;
+}
+
+#[async_trait]
+impl MfaRepositoryExt for AppState {
+    async fn set_pending_totp_secret(&self, user_id: Uuid, secret: &str) -> RepositoryResult<User> {
+        let query = r#"
+            UPDATE users SET totp_secret = $2, updated_at = now()
+            WHERE id = $1 AND totp_enabled_at IS NULL RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .bind(secret)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn enable_totp(&self, user_id: Uuid, code_hashes: &[String]) -> RepositoryResult<User> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let query = r#"
+            UPDATE users SET totp_enabled_at = now(), updated_at = now()
+            WHERE id = $1 AND totp_secret IS NOT NULL AND totp_enabled_at IS NULL RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        insert_recovery_codes(&mut tx, user_id, code_hashes).await?;
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    async fn disable_totp(&self, user_id: Uuid) -> RepositoryResult<User> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let query = r#"
+            UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, updated_at = now()
+            WHERE id = $1 RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    async fn replace_recovery_codes(&self, user_id: Uuid, code_hashes: &[String]) -> RepositoryResult<()> {
+        let mut tx = self.db_pool.begin().await?;
+        insert_recovery_codes(&mut tx, user_id, code_hashes).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn list_unused_recovery_codes(&self, user_id: Uuid) -> RepositoryResult<Vec<RecoveryCode>> {
+        let query = r#"
+            SELECT * FROM user_recovery_codes WHERE user_id = $1 AND used_at IS NULL
+        "#;
+
+        let codes = sqlx::query_as::<_, RecoveryCode>(query)
+            .bind(user_id)
+            .fetch_all(&*self.db_pool)
+            .await?;
+
+        Ok(codes)
+    }
+
+    async fn use_recovery_code(&self, code_id: Uuid) -> RepositoryResult<bool> {
+        // The `used_at` condition makes concurrent uses of the same code race safely.
+        let query = r#"
+            UPDATE user_recovery_codes SET used_at = now() WHERE id = $1 AND used_at IS NULL
+        "#;
+
+        let result = sqlx::query(query)
+            .bind(code_id)
+            .execute(&*self.db_pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+/// Replaces every recovery code of the user, previously issued codes stop working.
+async fn insert_recovery_codes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: Uuid,
+    code_hashes: &[String],
+) -> RepositoryResult<()> {
+    sqlx::query("DELETE FROM user_recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    for code_hash in code_hashes {
+        sqlx::query("INSERT INTO user_recovery_codes (user_id, code_hash, created_at) VALUES ($1, $2, now())")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    Ok(())
+}
@@ -1,3 +1,7 @@
 pub mod user_repository;
+pub mod mfa_repository;
+pub mod api_key_repository;
+pub mod oauth_client_repository;
+pub mod webauthn_repository;
 
 pub type RepositoryResult<T> = Result<T, sqlx::Error>;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use async_trait::async_trait;
+use crate::application::{
+    repository::RepositoryResult,
+    state::AppState,
+};
+use crate::domain::entities::oauth_client::{NewOAuthClient, OAuthClient};
+
+#[async_trait]
+pub trait OAuthClientRepositoryExt {
+    async fn create_oauth_client(&self, new_client: &NewOAuthClient) -> RepositoryResult<OAuthClient>;
+    async fn list_oauth_clients(&self) -> RepositoryResult<Vec<OAuthClient>>;
+    async fn get_oauth_client(&self, client_id: &str) -> RepositoryResult<Option<OAuthClient>>;
+    async fn delete_oauth_client(&self, client_id: &str) -> RepositoryResult<OAuthClient>;
+}
+
+#[async_trait]
+impl OAuthClientRepositoryExt for AppState {
+    async fn create_oauth_client(&self, new_client: &NewOAuthClient) -> RepositoryResult<OAuthClient> {
+        let query = r#"
+            INSERT INTO oauth_clients (client_id, client_secret_hash, name, redirect_uris, grant_types, scopes, roles, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now(), now())
+            RETURNING *
+        "#;
+
+        let client = sqlx::query_as::<_, OAuthClient>(query)
+            .bind(&new_client.client_id)
+            .bind(&new_client.client_secret_hash)
+            .bind(&new_client.name)
+            .bind(&new_client.redirect_uris)
+            .bind(&new_client.grant_types)
+            .bind(&new_client.scopes)
+            .bind(&new_client.roles)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(client)
+    }
+
+    async fn list_oauth_clients(&self) -> RepositoryResult<Vec<OAuthClient>> {
+        let clients = sqlx::query_as::<_, OAuthClient>("SELECT * FROM oauth_clients ORDER BY created_at")
+            .fetch_all(&*self.db_pool)
+            .await?;
+
+        Ok(clients)
+    }
+
+    async fn get_oauth_client(&self, client_id: &str) -> RepositoryResult<Option<OAuthClient>> {
+        let client = sqlx::query_as::<_, OAuthClient>("SELECT * FROM oauth_clients WHERE client_id = $1")
+            .bind(client_id)
+            .fetch_optional(&*self.db_pool)
+            .await?;
+
+        Ok(client)
+    }
+
+    async fn delete_oauth_client(&self, client_id: &str) -> RepositoryResult<OAuthClient> {
+        let client = sqlx::query_as::<_, OAuthClient>("DELETE FROM oauth_clients WHERE client_id = $1 RETURNING *")
+            .bind(client_id)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(client)
+    }
+}
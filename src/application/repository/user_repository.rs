@@ -6,12 +6,18 @@ use crate::application::{
     state::SharedState,
 };
 use crate::application::state::AppState;
-use crate::domain::entities::user::User;
+use crate::domain::entities::user::{NewUser, User};
 
 #[async_trait]
 pub trait UserRepositoryExt {
     async fn get_user_by_identifier(&self, identifier: &str) -> RepositoryResult<Option<User>>;
     async fn get_user_by_id(&self, user_id: Uuid) -> RepositoryResult<User>;
+    async fn set_user_active(&self, user_id: Uuid, active: bool) -> RepositoryResult<User>;
+    async fn find_taken_user_fields(&self, username: &str, email: &str) -> RepositoryResult<Vec<&'static str>>;
+    async fn create_user(&self, new_user: &NewUser) -> RepositoryResult<User>;
+    async fn mark_email_verified(&self, user_id: Uuid) -> RepositoryResult<User>;
+    async fn update_password_hash(&self, user_id: Uuid, password_hash: &str) -> RepositoryResult<User>;
+    async fn upgrade_password_hash(&self, user_id: Uuid, old_hash: &str, new_hash: &str) -> RepositoryResult<bool>;
 }
 
 #[async_trait]
@@ -41,6 +47,104 @@ impl UserRepositoryExt for AppState {
 
         Ok(user)
     }
+
+    async fn set_user_active(&self, user_id: Uuid, active: bool) -> RepositoryResult<User> {
+        let query = r#"
+            UPDATE users SET active = $2, updated_at = now() WHERE id = $1 RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .bind(active)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn find_taken_user_fields(&self, username: &str, email: &str) -> RepositoryResult<Vec<&'static str>> {
+        let query = r#"
+            SELECT username, email FROM users WHERE username = $1 OR email = $2
+        "#;
+
+        let rows: Vec<(String, String)> = sqlx::query_as(query)
+            .bind(username)
+            .bind(email)
+            .fetch_all(&*self.db_pool)
+            .await?;
+
+        let mut fields = Vec::new();
+        if rows.iter().any(|(taken_username, _)| taken_username == username) {
+            fields.push("username");
+        }
+        if rows.iter().any(|(_, taken_email)| taken_email == email) {
+            fields.push("email");
+        }
+        Ok(fields)
+    }
+
+    async fn create_user(&self, new_user: &NewUser) -> RepositoryResult<User> {
+        let query = r#"
+            INSERT INTO users (name, username, email, password_hash, active, roles, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, TRUE, $5, now(), now())
+            RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(&new_user.name)
+            .bind(&new_user.username)
+            .bind(&new_user.email)
+            .bind(&new_user.password_hash)
+            .bind(&new_user.roles)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn mark_email_verified(&self, user_id: Uuid) -> RepositoryResult<User> {
+        let query = r#"
+            UPDATE users SET email_verified_at = COALESCE(email_verified_at, now()), updated_at = now()
+            WHERE id = $1 RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    async fn update_password_hash(&self, user_id: Uuid, password_hash: &str) -> RepositoryResult<User> {
+        let query = r#"
+            UPDATE users SET password_hash = $2, updated_at = now() WHERE id = $1 RETURNING *
+        "#;
+
+        let user = sqlx::query_as::<_, User>(query)
+            .bind(user_id)
+            .bind(password_hash)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(user)
+    }
+
+    /// Replaces a hash by a stronger one of the same password, unless the password was changed in the meantime.
+    async fn upgrade_password_hash(&self, user_id: Uuid, old_hash: &str, new_hash: &str) -> RepositoryResult<bool> {
+        let query = r#"
+            UPDATE users SET password_hash = $3 WHERE id = $1 AND password_hash = $2
+        "#;
+
+        let result = sqlx::query(query)
+            .bind(user_id)
+            .bind(old_hash)
+            .bind(new_hash)
+            .execute(&*self.db_pool)
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
 }
 
 pub async fn list(state: &SharedState) -> RepositoryResult<Vec<User>> {
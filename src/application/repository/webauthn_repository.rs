@@ -0,0 +1,93 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+use crate::application::{
+    repository::RepositoryResult,
+    state::AppState,
+};
+use crate::domain::entities::webauthn_credential::{NewWebAuthnCredential, WebAuthnCredential};
+
+#[async_trait]
+pub trait WebAuthnRepositoryExt {
+    async fn create_webauthn_credential(&self, new_credential: &NewWebAuthnCredential) -> RepositoryResult<WebAuthnCredential>;
+    async fn list_webauthn_credentials(&self, user_id: Uuid) -> RepositoryResult<Vec<WebAuthnCredential>>;
+    async fn get_webauthn_credential(&self, credential_id: &str) -> RepositoryResult<Option<WebAuthnCredential>>;
+    async fn update_webauthn_sign_count(&self, id: Uuid, sign_count: i64) -> RepositoryResult<()>;
+    async fn delete_webauthn_credential(&self, user_id: Uuid, id: Uuid) -> RepositoryResult<WebAuthnCredential>;
+}
+
+#[async_trait]
+impl WebAuthnRepositoryExt for AppState {
+    async fn create_webauthn_credential(&self, new_credential: &NewWebAuthnCredential) -> RepositoryResult<WebAuthnCredential> {
+        let query = r#"
+            INSERT INTO webauthn_credentials (user_id, credential_id, public_key, algorithm, sign_count, name, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, now())
+            RETURNING *
+        "#;
+
+        let credential = sqlx::query_as::<_, WebAuthnCredential>(query)
+            .bind(new_credential.user_id)
+            .bind(&new_credential.credential_id)
+            .bind(&new_credential.public_key)
+            .bind(new_credential.algorithm)
+            .bind(new_credential.sign_count)
+            .bind(&new_credential.name)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(credential)
+    }
+
+    async fn list_webauthn_credentials(&self, user_id: Uuid) -> RepositoryResult<Vec<WebAuthnCredential>> {
+        let query = r#"
+            SELECT * FROM webauthn_credentials WHERE user_id = $1 ORDER BY created_at DESC
+        "#;
+
+        let credentials = sqlx::query_as::<_, WebAuthnCredential>(query)
+            .bind(user_id)
+            .fetch_all(&*self.db_pool)
+            .await?;
+
+        Ok(credentials)
+    }
+
+    async fn get_webauthn_credential(&self, credential_id: &str) -> RepositoryResult<Option<WebAuthnCredential>> {
+        let query = r#"
+            SELECT * FROM webauthn_credentials WHERE credential_id = $1
+        "#;
+
+        let credential = sqlx::query_as::<_, WebAuthnCredential>(query)
+            .bind(credential_id)
+            .fetch_optional(&*self.db_pool)
+            .await?;
+
+        Ok(credential)
+    }
+
+    async fn update_webauthn_sign_count(&self, id: Uuid, sign_count: i64) -> RepositoryResult<()> {
+        let query = r#"
+            UPDATE webauthn_credentials SET sign_count = $2, last_used_at = now() WHERE id = $1
+        "#;
+
+        sqlx::query(query)
+            .bind(id)
+            .bind(sign_count)
+            .execute(&*self.db_pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_webauthn_credential(&self, user_id: Uuid, id: Uuid) -> RepositoryResult<WebAuthnCredential> {
+        let query = r#"
+            DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2 RETURNING *
+        "#;
+
+        let credential = sqlx::query_as::<_, WebAuthnCredential>(query)
+            .bind(id)
+            .bind(user_id)
+            .fetch_one(&*self.db_pool)
+            .await?;
+
+        Ok(credential)
+    }
+}
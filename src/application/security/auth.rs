@@ -1,15 +1,19 @@
 use axum::http::StatusCode;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use thiserror::Error;
 use uuid::Uuid;
 use crate::api::{ApiError, ApiErrorCode, ApiErrorKind, ApiErrorResponse};
 use crate::application::{
     config::Config,
-    security::jwt::{AccessClaim, ClaimsMethods, JwtTokenType},
-    service::token_service,
+    repository::user_repository::UserRepositoryExt,
+    security::jwt::{AccessClaim, Actor, ClaimsMethods, EmailVerificationClaim, JwtTokenType, MagicLinkClaim, MfaPendingClaim, RefreshClaim, decode_token},
+    security::role::{Role, Roles},
+    service::session_service::{self, ClientInfo},
+    service::token_service::{self, RefreshFamilyStatus},
     state::SharedState,
 };
 use crate::domain::entities::user::User;
+use crate::infra::mail::MailError;
 
 #[derive(Debug, Error)]
 pub enum AuthError {
@@ -21,20 +25,44 @@ pub enum AuthError {
     TokenCreationError,
     #[error("invalid token")]
     InvalidToken,
+    #[error("refresh token reuse detected")]
+    RefreshTokenReused,
     #[error("password cannot be empty")]
     EmptyPassword,
     #[error("invalid password hash format")]
     InvalidHashFormat,
     #[error("error while hashing password")]
     HashingError,
+    #[error("too many passwords being hashed, try again in {0} seconds")]
+    HashingOverloaded(u64),
     #[error("invalid bearer token")]
     InvalidBearerToken,
     #[error("invalid authorization header")]
     InvalidAuthorizationHeader,
+    #[error("insufficient permissions")]
+    Forbidden,
+    #[error("invalid two-factor authentication code")]
+    InvalidMfaCode,
+    #[error("two-factor authentication is already enabled")]
+    MfaAlreadyEnabled,
+    #[error("two-factor authentication enrollment not started")]
+    MfaNotEnrolled,
+    #[error("session not found")]
+    SessionNotFound,
+    #[error("maximum of {0} simultaneous sessions reached")]
+    SessionLimitReached(usize),
+    #[error("invalid webauthn response: {0}")]
+    InvalidWebAuthnResponse(String),
+    #[error("too many failed login attempts, try again in {0} seconds")]
+    AccountLocked(u64),
+    #[error("unknown audience")]
+    InvalidAudience,
     #[error(transparent)]
     SQLxError(#[from] sqlx::Error),
     #[error(transparent)]
     RedisError(#[from] redis::RedisError),
+    #[error(transparent)]
+    MailError(#[from] MailError),
 }
 
 impl From<AuthError> for ApiError {
@@ -44,13 +72,30 @@ impl From<AuthError> for ApiError {
             AuthError::MissingCredentials => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationMissingCredentials),
             AuthError::TokenCreationError => (StatusCode::INTERNAL_SERVER_ERROR, ApiErrorCode::AuthenticationTokenCreationError),
             AuthError::InvalidToken => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationInvalidToken),
+            AuthError::RefreshTokenReused => (StatusCode::UNAUTHORIZED, ApiErrorCode::AuthenticationInvalidToken),
             AuthError::SQLxError(_) => (StatusCode::INTERNAL_SERVER_ERROR, ApiErrorCode::DatabaseError),
             AuthError::EmptyPassword => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationMissingCredentials),
             AuthError::InvalidHashFormat => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationHashingPasswordError),
             AuthError::HashingError => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationHashingPasswordError),
+            AuthError::HashingOverloaded(_) => (StatusCode::SERVICE_UNAVAILABLE, ApiErrorCode::AuthenticationHashingOverloaded),
             AuthError::InvalidBearerToken => (StatusCode::UNAUTHORIZED, ApiErrorCode::AuthenticationForbidden),
             AuthError::InvalidAuthorizationHeader => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationMissingCredentials),
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, ApiErrorCode::AuthenticationForbidden),
+            AuthError::InvalidMfaCode => (StatusCode::UNAUTHORIZED, ApiErrorCode::AuthenticationInvalidMfaCode),
+            AuthError::MfaAlreadyEnabled => (StatusCode::CONFLICT, ApiErrorCode::AuthenticationMfaAlreadyEnabled),
+            AuthError::MfaNotEnrolled => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationMfaNotEnrolled),
+            AuthError::SessionNotFound => (StatusCode::NOT_FOUND, ApiErrorCode::SessionNotFound),
+            AuthError::SessionLimitReached(_) => (StatusCode::CONFLICT, ApiErrorCode::AuthenticationSessionLimitReached),
+            AuthError::InvalidWebAuthnResponse(_) => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationInvalidWebauthnResponse),
+            AuthError::AccountLocked(_) => (StatusCode::TOO_MANY_REQUESTS, ApiErrorCode::AuthenticationAccountLocked),
+            AuthError::InvalidAudience => (StatusCode::BAD_REQUEST, ApiErrorCode::AuthenticationInvalidAudience),
             AuthError::RedisError(_) => (StatusCode::INTERNAL_SERVER_ERROR, ApiErrorCode::RedisError),
+            AuthError::MailError(_) => (StatusCode::INTERNAL_SERVER_ERROR, ApiErrorCode::MailError),
+        };
+
+        let retry_after = match auth_error {
+            AuthError::AccountLocked(seconds) | AuthError::HashingOverloaded(seconds) => Some(seconds),
+            _ => None,
         };
 
         let error_response = ApiErrorResponse::new(&auth_error.to_string())
@@ -60,16 +105,62 @@ impl From<AuthError> for ApiError {
         Self {
             status: status_code.as_u16(),
             errors: vec![error_response],
+            retry_after,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct JwtToken {
+    #[serde(rename = "token")]
     pub access_token: String,
+    pub refresh_token: String,
+    #[serde(skip)]
+    pub access_claim: AccessClaim,
+    #[serde(skip)]
+    pub refresh_claim: RefreshClaim,
+}
+
+/// Audience of a new token pair: the one requested by the client, or the default audience of the service.
+///
+/// Routes guarded by `JWT_ADMIN_AUDIENCE` only accept tokens issued for it, which must be requested explicitly.
+pub fn token_audience(requested: Option<&str>, config: &Config) -> Result<Vec<String>, AuthError> {
+    let Some(requested) = requested else {
+        return Ok(config.default_audience())
+    };
+    if !config.accepted_audiences().contains(&requested) {
+        tracing::error!("unknown audience requested: {}", requested);
+        return Err(AuthError::InvalidAudience)
+    }
+    Ok(vec![requested.to_owned()])
+}
+
+/// Creates an access/refresh token pair starting a new token family.
+pub fn create_token(user: &User, aud: &[String], config: &Config) -> Result<JwtToken, AuthError> {
+    let family_id = Uuid::new_v4().to_string();
+    create_token_in_family(user, &family_id, aud, config)
+}
+
+/// Creates an access/refresh token pair belonging to an existing token family.
+pub fn create_token_in_family(user: &User, family_id: &str, aud: &[String], config: &Config) -> Result<JwtToken, AuthError> {
+    create_client_token_in_family(user, family_id, None, aud, config)
+}
+
+/// OAuth client a token pair is issued to, along with the scopes the user granted it.
+#[derive(Debug, Clone)]
+pub struct ClientGrant {
+    pub client_id: String,
+    pub scopes: Vec<String>,
 }
 
-pub fn create_token(user: User, config: &Config) -> JwtToken {
+/// Creates an access/refresh token pair belonging to an existing token family, delegated to `grant` if any.
+pub fn create_client_token_in_family(
+    user: &User,
+    family_id: &str,
+    grant: Option<&ClientGrant>,
+    aud: &[String],
+    config: &Config,
+) -> Result<JwtToken, AuthError> {
     let now = chrono::Utc::now();
     let iat = now.timestamp() as usize;
     let sub = user.id.to_string();
@@ -79,22 +170,224 @@ pub fn create_token(user: User, config: &Config) -> JwtToken {
 
     let access_claim = AccessClaim {
         sub: sub.clone(),
-        jti: access_token_id.clone(),
+        iss: config.jwt_issuer.to_owned(),
+        aud: aud.to_vec(),
+        jti: access_token_id,
         iat,
+        iat_ms: Some(now.timestamp_millis() as u64),
         exp: access_token_exp,
         typ: JwtTokenType::AccessToken as u8,
-        roles: user.roles.clone(),
+        roles: Roles::parse(&user.roles),
+        fid: family_id.to_owned(),
+        scopes: grant.map(|grant| grant.scopes.clone()),
+        cid: grant.map(|grant| grant.client_id.to_owned()),
+        act: None,
     };
 
-    let access_token = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &access_claim,
-        &jsonwebtoken::EncodingKey::from_secret(config.jwt_secret.as_ref())
-    ).unwrap();
+    let refresh_token_id = Uuid::new_v4().to_string();
+    let refresh_token_exp = (now + chrono::Duration::seconds(config.jwt_exp_refresh_token_second)).timestamp() as usize;
+
+    let refresh_claim = RefreshClaim {
+        sub,
+        iss: config.jwt_issuer.to_owned(),
+        aud: aud.to_vec(),
+        jti: refresh_token_id,
+        iat,
+        iat_ms: Some(now.timestamp_millis() as u64),
+        exp: refresh_token_exp,
+        typ: JwtTokenType::RefreshToken as u8,
+        fid: family_id.to_owned(),
+        scopes: grant.map(|grant| grant.scopes.clone()),
+        cid: grant.map(|grant| grant.client_id.to_owned()),
+    };
+
+    let access_token = encode_token(&access_claim, config)?;
+    let refresh_token = encode_token(&refresh_claim, config)?;
+
+    Ok(JwtToken {
+        access_token,
+        refresh_token,
+        access_claim,
+        refresh_claim,
+    })
+}
+
+/// Creates a signed token proving that `user` owns their current email address.
+pub fn create_email_verification_token(user: &User, config: &Config) -> Result<String, AuthError> {
+    let now = chrono::Utc::now();
+
+    let claims = EmailVerificationClaim {
+        sub: user.id.to_string(),
+        iss: config.jwt_issuer.to_owned(),
+        aud: config.default_audience(),
+        jti: Uuid::new_v4().to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + chrono::Duration::seconds(config.email_verification_exp_seconds)).timestamp() as usize,
+        typ: JwtTokenType::EmailVerificationToken as u8,
+        email: user.email.to_owned(),
+    };
+
+    encode_token(&claims, config)
+}
+
+/// Creates a short-lived signed token logging `user` in, sent by email to their current address.
+pub fn create_magic_link_token(user: &User, config: &Config) -> Result<String, AuthError> {
+    let now = chrono::Utc::now();
+
+    let claims = MagicLinkClaim {
+        sub: user.id.to_string(),
+        iss: config.jwt_issuer.to_owned(),
+        aud: config.default_audience(),
+        jti: Uuid::new_v4().to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + chrono::Duration::seconds(config.magic_link_exp_seconds)).timestamp() as usize,
+        typ: JwtTokenType::MagicLinkToken as u8,
+        email: user.email.to_owned(),
+    };
+
+    encode_token(&claims, config)
+}
+
+/// Second step of a login with two-factor authentication enabled, exchanged at `/auth/mfa/verify`.
+#[derive(Debug, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+/// Creates a short-lived token proving that `user` passed the first factor.
+///
+/// The challenge carries the audience requested at login, given to the token pair it is exchanged for.
+pub fn create_mfa_challenge(user: &User, aud: &[String], config: &Config) -> Result<MfaChallenge, AuthError> {
+    let now = chrono::Utc::now();
+
+    let claims = MfaPendingClaim {
+        sub: user.id.to_string(),
+        iss: config.jwt_issuer.to_owned(),
+        aud: aud.to_vec(),
+        jti: Uuid::new_v4().to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + chrono::Duration::seconds(config.mfa_pending_exp_seconds)).timestamp() as usize,
+        typ: JwtTokenType::MfaPendingToken as u8,
+    };
+
+    Ok(MfaChallenge {
+        mfa_required: true,
+        mfa_token: encode_token(&claims, config)?,
+        expires_in: config.mfa_pending_exp_seconds,
+    })
+}
+
+/// Short-lived access token letting an admin act as another user, it cannot be refreshed.
+#[derive(Debug, Serialize)]
+pub struct ImpersonationToken {
+    pub token: String,
+    pub expires_in: i64,
+    #[serde(skip)]
+    pub access_claim: AccessClaim,
+}
+
+/// Creates an access token for `user` carrying `actor_sub` in its `act` claim.
+pub fn create_impersonation_token(user: &User, actor_sub: &str, config: &Config) -> Result<ImpersonationToken, AuthError> {
+    let now = chrono::Utc::now();
+
+    let access_claim = AccessClaim {
+        sub: user.id.to_string(),
+        iss: config.jwt_issuer.to_owned(),
+        aud: config.default_audience(),
+        jti: Uuid::new_v4().to_string(),
+        iat: now.timestamp() as usize,
+        iat_ms: Some(now.timestamp_millis() as u64),
+        exp: (now + chrono::Duration::seconds(config.impersonation_exp_seconds)).timestamp() as usize,
+        typ: JwtTokenType::AccessToken as u8,
+        roles: Roles::parse(&user.roles),
+        fid: Uuid::new_v4().to_string(),
+        scopes: None,
+        cid: None,
+        act: Some(Actor { sub: actor_sub.to_owned() }),
+    };
+
+    Ok(ImpersonationToken {
+        token: encode_token(&access_claim, config)?,
+        expires_in: config.impersonation_exp_seconds,
+        access_claim,
+    })
+}
+
+/// Signs `claims` with the current signing key of the key ring.
+pub fn encode_token<T: Serialize>(claims: &T, config: &Config) -> Result<String, AuthError> {
+    let signing_key = &config.jwt_keys.signing;
+    jsonwebtoken::encode(&signing_key.header(), claims, &signing_key.encoding)
+        .map_err(|_| AuthError::TokenCreationError)
+}
+
+/// Issues a new token pair for a freshly authenticated user, registering its refresh token family and session.
+pub async fn issue_token(user: &User, aud: &[String], client: &ClientInfo, state: &SharedState) -> Result<JwtToken, AuthError> {
+    let token = create_token(user, aud, &state.config)?;
+    session_service::create_session(user, &token, client, state).await?;
+    token_service::store_refresh_family(&token.refresh_claim, state).await?;
+    Ok(token)
+}
 
-    JwtToken {
-        access_token
+/// Exchanges a refresh token for a new token pair, rotating the refresh token.
+pub async fn refresh_token(refresh_token: &str, state: &SharedState) -> Result<JwtToken, AuthError> {
+    let claims = decode_refresh_token(refresh_token, state).await?;
+
+    // Tokens issued to OAuth clients are refreshed at `/oauth/token`, where the client authenticates.
+    if claims.cid.is_some() {
+        return Err(AuthError::InvalidToken)
     }
+
+    rotate_refresh_token(&claims, state).await
+}
+
+pub async fn decode_refresh_token(refresh_token: &str, state: &SharedState) -> Result<RefreshClaim, AuthError> {
+    let claims = decode_token::<RefreshClaim>(refresh_token, &state.config)?;
+    if claims.get_typ() != JwtTokenType::RefreshToken {
+        return Err(AuthError::InvalidToken)
+    }
+
+    if state.config.jwt_enable_revoked_tokens {
+        validate_revoked(&claims, state).await?
+    }
+    Ok(claims)
+}
+
+/// Issues the token pair following `claims` in its family.
+///
+/// Presenting a refresh token that was already rotated revokes the whole family.
+pub async fn rotate_refresh_token(claims: &RefreshClaim, state: &SharedState) -> Result<JwtToken, AuthError> {
+    let user_id = claims.sub.parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AuthError::WrongCredentials,
+            _ => AuthError::from(e),
+        })?;
+
+    if !user.active {
+        return Err(AuthError::WrongCredentials)
+    }
+
+    let grant = claims.cid.as_ref().map(|client_id| ClientGrant {
+        client_id: client_id.to_owned(),
+        scopes: claims.scopes.clone().unwrap_or_default(),
+    });
+    let token = create_client_token_in_family(&user, &claims.fid, grant.as_ref(), &claims.aud, &state.config)?;
+
+    match token_service::rotate_refresh_family(claims, &token.refresh_claim, state).await? {
+        RefreshFamilyStatus::Current => {}
+        RefreshFamilyStatus::Reused => {
+            tracing::error!("refresh token reuse detected, revoking family: {:#?}", claims);
+            session_service::revoke_session(&claims.sub, &claims.fid, state).await?;
+            return Err(AuthError::RefreshTokenReused)
+        }
+        RefreshFamilyStatus::Unknown => return Err(AuthError::InvalidToken),
+    }
+    session_service::rotate_session(&token, state).await?;
+
+    Ok(token)
 }
 
 pub async fn validate_revoked<T: std::fmt::Debug + ClaimsMethods + Send + Sync>(
@@ -106,4 +399,106 @@ pub async fn validate_revoked<T: std::fmt::Debug + ClaimsMethods + Send + Sync>(
         Err(AuthError::WrongCredentials)?
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn require_role(claims: &AccessClaim, role: &Role) -> Result<(), AuthError> {
+    if !claims.roles.contains(role) {
+        tracing::error!("access denied ({} role required): {:#?}", role, claims);
+        return Err(AuthError::Forbidden)
+    }
+    Ok(())
+}
+
+/// Rejects claims not issued for `audience`, the routes guarded by it only accept tokens requested for it.
+pub fn require_audience(claims: &AccessClaim, audience: &str) -> Result<(), AuthError> {
+    if !claims.has_audience(audience) {
+        tracing::error!("token not issued for audience {}: {:#?}", audience, claims);
+        return Err(AuthError::InvalidToken)
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::config;
+    use std::net::Ipv4Addr;
+    use crate::application::security::jwt::decode_token;
+    use crate::application::state::test_state;
+    use crate::domain::entities::user::NewUser;
+
+    fn user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+            password_hash: String::new(),
+            active: true,
+            roles: "admin".to_owned(),
+            email_verified_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn default_audience_excludes_admin() {
+        let mut config = config::test_config();
+        config.jwt_audience.push("admin".to_owned());
+
+        assert_eq!(token_audience(None, &config).unwrap(), vec!["api".to_owned()]);
+        assert_eq!(token_audience(Some("admin"), &config).unwrap(), vec!["admin".to_owned()]);
+        assert!(matches!(token_audience(Some("other"), &config), Err(AuthError::InvalidAudience)));
+    }
+
+    #[test]
+    fn token_of_another_audience_is_rejected() {
+        let config = config::test_config();
+
+        let api_token = create_token(&user(), &token_audience(None, &config).unwrap(), &config).unwrap();
+        let claims = decode_token::<AccessClaim>(&api_token.access_token, &config).unwrap();
+        assert!(matches!(require_audience(&claims, "admin"), Err(AuthError::InvalidToken)));
+        assert!(require_audience(&claims, "api").is_ok());
+
+        let admin_token = create_token(&user(), &token_audience(Some("admin"), &config).unwrap(), &config).unwrap();
+        let claims = decode_token::<AccessClaim>(&admin_token.access_token, &config).unwrap();
+        assert!(require_audience(&claims, "admin").is_ok());
+        assert!(matches!(require_audience(&claims, "api"), Err(AuthError::InvalidToken)));
+    }
+
+    #[test]
+    fn token_of_a_foreign_audience_does_not_decode() {
+        let config = config::test_config();
+        let token = create_token(&user(), &["other".to_owned()], &config).unwrap();
+
+        assert!(decode_token::<AccessClaim>(&token.access_token, &config).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn reused_refresh_token_revokes_the_older_access_tokens_of_its_family() {
+        let state = test_state().await;
+        let name = format!("refresh-{}", Uuid::new_v4().simple());
+        let new_user = NewUser {
+            name: name.to_owned(),
+            username: name.to_owned(),
+            email: format!("{}@example.com", name),
+            password_hash: String::new(),
+            roles: "user".to_owned(),
+        };
+        let user = state.create_user(&new_user).await.unwrap();
+        let client = ClientInfo { ip: Ipv4Addr::LOCALHOST.into(), user_agent: None };
+
+        let first = issue_token(&user, &token_audience(None, &state.config).unwrap(), &client, &state).await.unwrap();
+        let second = refresh_token(&first.refresh_token, &state).await.unwrap();
+        validate_revoked(&first.access_claim, &state).await.unwrap();
+
+        assert!(matches!(refresh_token(&first.refresh_token, &state).await, Err(AuthError::RefreshTokenReused)));
+        assert!(matches!(validate_revoked(&first.access_claim, &state).await, Err(AuthError::WrongCredentials)));
+        assert!(matches!(validate_revoked(&second.access_claim, &state).await, Err(AuthError::WrongCredentials)));
+        assert!(refresh_token(&second.refresh_token, &state).await.is_err());
+    }
+}
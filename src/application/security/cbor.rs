@@ -0,0 +1,240 @@
+use thiserror::Error;
+
+/// Nesting limit, the structures of WebAuthn are only a few levels deep.
+const MAX_DEPTH: usize = 16;
+
+/// Decoded CBOR data item, see RFC 8949.
+///
+/// Only what WebAuthn authenticators emit is supported: definite lengths and no floating point numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Self::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Looks up the value of `key` when this is a map.
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match self {
+            Self::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CborError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unsupported data item: {0}")]
+    Unsupported(&'static str),
+    #[error("invalid UTF-8 text string")]
+    InvalidText,
+    #[error("nesting too deep")]
+    TooDeep,
+}
+
+/// Decodes the data item at the start of `input`, returning it along with the number of bytes read.
+pub fn decode(input: &[u8]) -> Result<(Value, usize), CborError> {
+    let mut decoder = Decoder { input, position: 0 };
+    let value = decoder.value(0)?;
+    Ok((value, decoder.position))
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn value(&mut self, depth: usize) -> Result<Value, CborError> {
+        if depth > MAX_DEPTH {
+            return Err(CborError::TooDeep)
+        }
+
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+
+        let value = match major {
+            0 => Value::Integer(self.argument(info)? as i128),
+            1 => Value::Integer(-1 - self.argument(info)? as i128),
+            2 => {
+                let len = self.length(info)?;
+                Value::Bytes(self.take(len)?.to_vec())
+            }
+            3 => {
+                let len = self.length(info)?;
+                let text = std::str::from_utf8(self.take(len)?).map_err(|_| CborError::InvalidText)?;
+                Value::Text(text.to_owned())
+            }
+            4 => {
+                let len = self.length(info)?;
+                let items = (0..len).map(|_| self.value(depth + 1)).collect::<Result<_, _>>()?;
+                Value::Array(items)
+            }
+            5 => {
+                let len = self.length(info)?;
+                let entries = (0..len)
+                    .map(|_| Ok((self.value(depth + 1)?, self.value(depth + 1)?)))
+                    .collect::<Result<_, _>>()?;
+                Value::Map(entries)
+            }
+            // Tags carry no meaning for WebAuthn, the tagged item is returned as is.
+            6 => {
+                self.argument(info)?;
+                self.value(depth + 1)?
+            }
+            _ => match info {
+                20 => Value::Bool(false),
+                21 => Value::Bool(true),
+                22 | 23 => Value::Null,
+                25..=27 => return Err(CborError::Unsupported("floating point number")),
+                _ => return Err(CborError::Unsupported("simple value")),
+            },
+        };
+
+        Ok(value)
+    }
+
+    fn argument(&mut self, info: u8) -> Result<u64, CborError> {
+        let argument = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take_array()?) as u64,
+            26 => u32::from_be_bytes(self.take_array()?) as u64,
+            27 => u64::from_be_bytes(self.take_array()?),
+            31 => return Err(CborError::Unsupported("indefinite length")),
+            _ => return Err(CborError::Unsupported("reserved additional information")),
+        };
+        Ok(argument)
+    }
+
+    /// Length of a string or container, bounded by the remaining input so a forged length cannot exhaust memory.
+    fn length(&mut self, info: u8) -> Result<usize, CborError> {
+        let remaining = self.input.len() - self.position;
+        usize::try_from(self.argument(info)?)
+            .ok()
+            .filter(|len| *len <= remaining)
+            .ok_or(CborError::UnexpectedEnd)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], CborError> {
+        let end = self.position
+            .checked_add(len)
+            .filter(|end| *end <= self.input.len())
+            .ok_or(CborError::UnexpectedEnd)?;
+        let bytes = &self.input[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], CborError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_rfc_8949_examples() {
+        for (input, expected) in [
+            (&[0x00][..], Value::Integer(0)),
+            (&[0x18, 0x64], Value::Integer(100)),
+            (&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], Value::Integer(u64::MAX as i128)),
+            (&[0x38, 0x63], Value::Integer(-100)),
+            (&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], Value::Integer(-1 - u64::MAX as i128)),
+            (&[0x44, 0x01, 0x02, 0x03, 0x04], Value::Bytes(vec![1, 2, 3, 4])),
+            (&[0x64, 0x49, 0x45, 0x54, 0x46], Value::Text("IETF".to_owned())),
+            (&[0x83, 0x01, 0x82, 0x02, 0x03, 0x81, 0x04], Value::Array(vec![
+                Value::Integer(1),
+                Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+                Value::Array(vec![Value::Integer(4)]),
+            ])),
+            (&[0xa2, 0x01, 0x02, 0x61, 0x61, 0xf5], Value::Map(vec![
+                (Value::Integer(1), Value::Integer(2)),
+                (Value::Text("a".to_owned()), Value::Bool(true)),
+            ])),
+            (&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0], Value::Integer(1363896240)),
+            (&[0xf4], Value::Bool(false)),
+            (&[0xf6], Value::Null),
+        ] {
+            assert_eq!(decode(input).unwrap(), (expected, input.len()), "input {:02x?}", input);
+        }
+    }
+
+    #[test]
+    fn reports_the_length_of_the_first_item_only() {
+        assert_eq!(decode(&[0x42, 0xaa, 0xbb, 0xff, 0xff]).unwrap(), (Value::Bytes(vec![0xaa, 0xbb]), 3));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        for input in [
+            &[][..],
+            &[0x18],
+            &[0x19, 0x01],
+            &[0x44, 0x01, 0x02, 0x03],
+            &[0x83, 0x01, 0x02],
+            &[0xa1, 0x01],
+        ] {
+            assert!(matches!(decode(input), Err(CborError::UnexpectedEnd)), "input {:02x?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_forged_lengths_without_allocating() {
+        // Byte string, array and map claiming u64::MAX items in a few bytes of input.
+        for major in [0x5b, 0x9b, 0xbb] {
+            let input = [major, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+            assert!(matches!(decode(&input), Err(CborError::UnexpectedEnd)), "major {:02x}", major);
+        }
+        assert!(matches!(decode(&[0x7a, 0x7f, 0xff, 0xff, 0xff, 0x61]), Err(CborError::UnexpectedEnd)));
+    }
+
+    #[test]
+    fn limits_the_nesting_depth() {
+        let nested = |depth: usize| [vec![0x81; depth], vec![0x00]].concat();
+
+        assert!(decode(&nested(MAX_DEPTH)).is_ok());
+        assert!(matches!(decode(&nested(MAX_DEPTH + 1)), Err(CborError::TooDeep)));
+        assert!(matches!(decode(&[0xc1; 64]), Err(CborError::TooDeep)));
+    }
+
+    #[test]
+    fn rejects_unsupported_items() {
+        assert!(matches!(decode(&[0x5f, 0x41, 0x00, 0xff]), Err(CborError::Unsupported(_))));
+        assert!(matches!(decode(&[0xf9, 0x3c, 0x00]), Err(CborError::Unsupported(_))));
+        assert!(matches!(decode(&[0x1c]), Err(CborError::Unsupported(_))));
+        assert!(matches!(decode(&[0x62, 0xc3, 0x28]), Err(CborError::InvalidText)));
+    }
+}
@@ -1,31 +1,174 @@
 use std::fmt::Formatter;
-use jsonwebtoken::{EncodingKey, DecodingKey};
+use std::str::FromStr;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use ring::signature::KeyPair;
+use jsonwebtoken::{Algorithm, EncodingKey, DecodingKey, Header};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, EllipticCurveKeyType,
+    Jwk, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use crate::application::config::Config;
-use crate::application::security::auth::AuthError;
+use crate::application::security::{auth::AuthError, role::Roles};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessClaim {
     /// Subject.
     pub sub: String,
+    /// Issuer.
+    pub iss: String,
+    /// Audience.
+    pub aud: Vec<String>,
     /// JWT ID.
     pub jti: String,
     /// Issued time.
     pub iat: usize,
+    /// Issued time in milliseconds, orders the token against revocation watermarks set within the same second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<u64>,
     /// Expiration time.
     pub exp: usize,
     /// Token type.
     pub typ: u8,
     /// Roles.
-    pub roles: String,
+    pub roles: Roles,
+    /// Token family ID.
+    pub fid: String,
+    /// Permissions an API key or OAuth client is restricted to, `None` for tokens issued by a login.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// OAuth client the token was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+    /// Admin acting as the subject, set on impersonation tokens only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+/// Party acting on behalf of the subject, see RFC 8693 section 4.1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Actor {
+    /// Subject of the actor.
+    pub sub: String,
 }
 
-#[derive(Debug, Copy, Clone)]
+impl AccessClaim {
+    pub fn is_impersonated(&self) -> bool {
+        self.act.is_some()
+    }
+
+    /// Subject actually performing the request, the admin for impersonation tokens.
+    pub fn actor_sub(&self) -> &str {
+        self.act.as_ref().map_or(&self.sub, |act| &act.sub)
+    }
+
+    pub fn has_audience(&self, audience: &str) -> bool {
+        self.aud.iter().any(|aud| aud == audience)
+    }
+
+    /// Tells whether the claims were delegated to an API key or an OAuth client rather than issued by a login.
+    pub fn is_delegated(&self) -> bool {
+        self.scopes.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshClaim {
+    /// Subject.
+    pub sub: String,
+    /// Issuer.
+    pub iss: String,
+    /// Audience.
+    pub aud: Vec<String>,
+    /// JWT ID.
+    pub jti: String,
+    /// Issued time.
+    pub iat: usize,
+    /// Issued time in milliseconds, orders the token against revocation watermarks set within the same second.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<u64>,
+    /// Expiration time.
+    pub exp: usize,
+    /// Token type.
+    pub typ: u8,
+    /// Token family ID, shared by every refresh token rotated from the same login.
+    pub fid: String,
+    /// Scopes granted to the OAuth client, carried over to rotated tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// OAuth client the token was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cid: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerificationClaim {
+    /// Subject.
+    pub sub: String,
+    /// Issuer.
+    pub iss: String,
+    /// Audience.
+    pub aud: Vec<String>,
+    /// JWT ID.
+    pub jti: String,
+    /// Issued time.
+    pub iat: usize,
+    /// Expiration time.
+    pub exp: usize,
+    /// Token type.
+    pub typ: u8,
+    /// Email address being verified, the token is void once the user changes it.
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkClaim {
+    /// Subject.
+    pub sub: String,
+    /// Issuer.
+    pub iss: String,
+    /// Audience.
+    pub aud: Vec<String>,
+    /// JWT ID.
+    pub jti: String,
+    /// Issued time.
+    pub iat: usize,
+    /// Expiration time.
+    pub exp: usize,
+    /// Token type.
+    pub typ: u8,
+    /// Email address the link was sent to, the link is void once the user changes it.
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MfaPendingClaim {
+    /// Subject.
+    pub sub: String,
+    /// Issuer.
+    pub iss: String,
+    /// Audience.
+    pub aud: Vec<String>,
+    /// JWT ID.
+    pub jti: String,
+    /// Issued time.
+    pub iat: usize,
+    /// Expiration time.
+    pub exp: usize,
+    /// Token type.
+    pub typ: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum JwtTokenType {
-    AccessToken,
-    RefreshToken,
-    UnknownToken,
+    AccessToken = 0,
+    RefreshToken = 1,
+    EmailVerificationToken = 2,
+    MfaPendingToken = 3,
+    MagicLinkToken = 4,
+    UnknownToken = u8::MAX,
 }
 
 impl From<u8> for JwtTokenType {
@@ -33,6 +176,9 @@ impl From<u8> for JwtTokenType {
         match value {
             0 => Self::AccessToken,
             1 => Self::RefreshToken,
+            2 => Self::EmailVerificationToken,
+            3 => Self::MfaPendingToken,
+            4 => Self::MagicLinkToken,
             _ => Self::UnknownToken,
         }
     }
@@ -40,30 +186,151 @@ impl From<u8> for JwtTokenType {
 
 #[derive(Clone)]
 pub struct JwtKey {
+    /// Key ID, stamped into the `kid` header of issued tokens.
+    pub kid: String,
+    pub algorithm: Algorithm,
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
+    /// Public part of the key, `None` for symmetric keys which must never be published.
+    pub jwk: Option<Jwk>,
+    /// Unix timestamp after which the key is no longer accepted.
+    pub retire_at: Option<i64>,
 }
 
 impl std::fmt::Debug for JwtKey {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("JwtKeys").finish()
+        f.debug_struct("JwtKeys")
+            .field("kid", &self.kid)
+            .field("algorithm", &self.algorithm)
+            .finish()
     }
 }
 
 impl JwtKey {
-    pub fn new(secret: &[u8]) -> Self {
+    pub fn new(kid: &str, algorithm: Algorithm, secret: &[u8]) -> Self {
         Self {
+            kid: kid.to_owned(),
+            algorithm,
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+            retire_at: None,
+        }
+    }
+
+    /// Builds an asymmetric key from a PEM encoded private key.
+    ///
+    /// The public key is derived from the private key, so only one file is needed.
+    pub fn from_pem(kid: &str, algorithm: Algorithm, private_key: &[u8]) -> Result<Self, JwtKeyError> {
+        let der = pem::parse(private_key)?;
+
+        let (encoding, parameters) = match algorithm {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 |
+            Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => {
+                let key_pair = match der.tag() {
+                    "RSA PRIVATE KEY" => ring::rsa::KeyPair::from_der(der.contents()),
+                    _ => ring::rsa::KeyPair::from_pkcs8(der.contents()),
+                }.map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+                let components = ring::rsa::PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+
+                let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: BASE64_URL_SAFE_NO_PAD.encode(components.n),
+                    e: BASE64_URL_SAFE_NO_PAD.encode(components.e),
+                });
+                (EncodingKey::from_rsa_pem(private_key)?, parameters)
+            }
+            Algorithm::ES256 | Algorithm::ES384 => {
+                let (signing, curve, coordinate_len) = match algorithm {
+                    Algorithm::ES256 => (&ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING, EllipticCurve::P256, 32),
+                    _ => (&ring::signature::ECDSA_P384_SHA384_FIXED_SIGNING, EllipticCurve::P384, 48),
+                };
+                let rng = ring::rand::SystemRandom::new();
+                let key_pair = ring::signature::EcdsaKeyPair::from_pkcs8(signing, der.contents(), &rng)
+                    .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+
+                // Uncompressed point: 0x04 || x || y.
+                let point = key_pair.public_key().as_ref();
+                let parameters = AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    key_type: EllipticCurveKeyType::EC,
+                    curve,
+                    x: BASE64_URL_SAFE_NO_PAD.encode(&point[1..1 + coordinate_len]),
+                    y: BASE64_URL_SAFE_NO_PAD.encode(&point[1 + coordinate_len..]),
+                });
+                (EncodingKey::from_ec_pem(private_key)?, parameters)
+            }
+            Algorithm::EdDSA => {
+                let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents())
+                    .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+
+                let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: BASE64_URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+                });
+                (EncodingKey::from_ed_pem(private_key)?, parameters)
+            }
+            _ => return Err(JwtKeyError::UnsupportedAlgorithm(algorithm)),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(KeyAlgorithm::from_str(&format!("{:?}", algorithm))?),
+                key_id: Some(kid.to_owned()),
+                ..Default::default()
+            },
+            algorithm: parameters,
+        };
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            encoding,
+            decoding: DecodingKey::from_jwk(&jwk)?,
+            jwk: Some(jwk),
+            retire_at: None,
+        })
+    }
+
+    pub fn is_retired(&self, now: i64) -> bool {
+        self.retire_at.is_some_and(|retire_at| retire_at <= now)
+    }
+
+    pub fn header(&self) -> Header {
+        Header {
+            kid: Some(self.kid.clone()),
+            ..Header::new(self.algorithm)
         }
     }
 }
 
+#[derive(Debug, Error)]
+pub enum JwtKeyError {
+    #[error("unsupported signing algorithm: {0:?}")]
+    UnsupportedAlgorithm(Algorithm),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error(transparent)]
+    PemError(#[from] pem::PemError),
+    #[error(transparent)]
+    JwtError(#[from] jsonwebtoken::errors::Error),
+}
+
 pub trait ClaimsMethods {
     fn get_sub(&self) -> &str;
     fn get_exp(&self) -> usize;
     fn get_iat(&self) -> usize;
+    /// Issued time in milliseconds, as precise as the token allows.
+    fn get_iat_ms(&self) -> u64 {
+        self.get_iat() as u64 * 1000
+    }
     fn get_jti(&self) -> &str;
+    fn get_typ(&self) -> JwtTokenType;
+    /// Token family ID, for the tokens issued by a login and rotated by refreshes.
+    fn get_fid(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl ClaimsMethods for AccessClaim {
@@ -79,19 +346,161 @@ impl ClaimsMethods for AccessClaim {
         self.iat
     }
 
+    fn get_iat_ms(&self) -> u64 {
+        self.iat_ms.unwrap_or(self.iat as u64 * 1000)
+    }
+
+    fn get_jti(&self) -> &str {
+        &self.jti
+    }
+
+    fn get_typ(&self) -> JwtTokenType {
+        JwtTokenType::from(self.typ)
+    }
+
+    fn get_fid(&self) -> Option<&str> {
+        Some(&self.fid)
+    }
+}
+
+impl ClaimsMethods for RefreshClaim {
+    fn get_sub(&self) -> &str {
+        &self.sub
+    }
+
+    fn get_exp(&self) -> usize {
+        self.exp
+    }
+
+    fn get_iat(&self) -> usize {
+        self.iat
+    }
+
+    fn get_iat_ms(&self) -> u64 {
+        self.iat_ms.unwrap_or(self.iat as u64 * 1000)
+    }
+
     fn get_jti(&self) -> &str {
         &self.jti
     }
+
+    fn get_typ(&self) -> JwtTokenType {
+        JwtTokenType::from(self.typ)
+    }
+
+    fn get_fid(&self) -> Option<&str> {
+        Some(&self.fid)
+    }
+}
+
+impl ClaimsMethods for EmailVerificationClaim {
+    fn get_sub(&self) -> &str {
+        &self.sub
+    }
+
+    fn get_exp(&self) -> usize {
+        self.exp
+    }
+
+    fn get_iat(&self) -> usize {
+        self.iat
+    }
+
+    fn get_jti(&self) -> &str {
+        &self.jti
+    }
+
+    fn get_typ(&self) -> JwtTokenType {
+        JwtTokenType::from(self.typ)
+    }
+}
+
+impl ClaimsMethods for MagicLinkClaim {
+    fn get_sub(&self) -> &str {
+        &self.sub
+    }
+
+    fn get_exp(&self) -> usize {
+        self.exp
+    }
+
+    fn get_iat(&self) -> usize {
+        self.iat
+    }
+
+    fn get_jti(&self) -> &str {
+        &self.jti
+    }
+
+    fn get_typ(&self) -> JwtTokenType {
+        JwtTokenType::from(self.typ)
+    }
+}
+
+impl ClaimsMethods for MfaPendingClaim {
+    fn get_sub(&self) -> &str {
+        &self.sub
+    }
+
+    fn get_exp(&self) -> usize {
+        self.exp
+    }
+
+    fn get_iat(&self) -> usize {
+        self.iat
+    }
+
+    fn get_jti(&self) -> &str {
+        &self.jti
+    }
+
+    fn get_typ(&self) -> JwtTokenType {
+        JwtTokenType::from(self.typ)
+    }
 }
 
 pub fn decode_token<T: for<'de> serde::Deserialize<'de>>(token: &str, config: &Config)  -> Result<T, AuthError> {
-    let mut validation = jsonwebtoken::Validation::default();
+    let header = jsonwebtoken::decode_header(token).map_err(|_| {
+        tracing::error!("invalid bearer token header: {}", token);
+        AuthError::InvalidBearerToken
+    })?;
+
+    // Tokens issued before key IDs were introduced carry no `kid`, fall back to the signing key.
+    let key = match header.kid.as_deref() {
+        Some(kid) => config.jwt_keys.find(kid).ok_or_else(|| {
+            tracing::error!("unknown or retired key id: {}", kid);
+            AuthError::InvalidBearerToken
+        })?,
+        None => &config.jwt_keys.signing,
+    };
+
+    // Tokens of another environment or service must be rejected even when they share the key.
+    let mut validation = jsonwebtoken::Validation::new(key.algorithm);
     validation.leeway = config.jwt_validation_leeway_seconds as u64;
-    let token_data = jsonwebtoken::decode::<T>(token, &config.jwt_key.decoding, &validation)
+    validation.set_issuer(&[&config.jwt_issuer]);
+    validation.set_audience(&config.accepted_audiences());
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    let token_data = jsonwebtoken::decode::<T>(token, &key.decoding, &validation)
         .map_err(|_| {
             tracing::error!("invalid bearer token: {}", token);
             AuthError::InvalidBearerToken
         })?;
 
     Ok(token_data.claims)
-}
\ No newline at end of file
+}
+
+/// Audience required by [`crate::api::extractor::RequireAudience`] on top of the ones checked by `decode_token`,
+/// implemented by marker types.
+pub trait RequiredAudience: Send + Sync + 'static {
+    /// Configured audience, `None` when the routes accept any token of this service.
+    fn audience(config: &Config) -> Option<&str>;
+}
+
+/// Audience of the admin routes, see `JWT_ADMIN_AUDIENCE`.
+pub struct AdminAudience;
+
+impl RequiredAudience for AdminAudience {
+    fn audience(config: &Config) -> Option<&str> {
+        config.jwt_admin_audience.as_deref()
+    }
+}
@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+use jsonwebtoken::Algorithm;
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::application::security::jwt::{JwtKey, JwtKeyError};
+
+/// Set of JWT keys: exactly one key signs new tokens, the others are only accepted for verification.
+#[derive(Debug, Clone)]
+pub struct JwtKeyRing {
+    pub signing: JwtKey,
+    pub verification: Vec<JwtKey>,
+}
+
+impl JwtKeyRing {
+    pub fn new(signing: JwtKey) -> Self {
+        Self {
+            signing,
+            verification: Vec::new(),
+        }
+    }
+
+    /// Looks up a key that is still allowed to verify tokens by its `kid`.
+    pub fn find(&self, kid: &str) -> Option<&JwtKey> {
+        let now = chrono::Utc::now().timestamp();
+        std::iter::once(&self.signing)
+            .chain(self.verification.iter())
+            .find(|key| key.kid == kid && !key.is_retired(now))
+    }
+
+    /// Public keys of the ring, symmetric keys are never published.
+    pub fn jwks(&self) -> JwkSet {
+        let now = chrono::Utc::now().timestamp();
+        let keys = std::iter::once(&self.signing)
+            .chain(self.verification.iter())
+            .filter(|key| !key.is_retired(now))
+            .filter_map(|key| key.jwk.clone())
+            .collect();
+        JwkSet { keys }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStatus {
+    Signing,
+    Verification,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    /// PEM encoded private key, for asymmetric algorithms. Relative to the manifest directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key_path: Option<PathBuf>,
+    /// Environment variable holding the secret, for HMAC algorithms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_env: Option<String>,
+    pub status: KeyStatus,
+    /// Unix timestamp after which tokens signed with the key are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retire_at: Option<i64>,
+}
+
+impl KeyEntry {
+    fn load(&self, base_dir: &Path) -> Result<JwtKey, KeyRingError> {
+        let mut key = if let Some(secret_env) = &self.secret_env {
+            let secret = std::env::var(secret_env)
+                .map_err(|_| KeyRingError::MissingSecret(secret_env.to_owned()))?;
+            JwtKey::new(&self.kid, self.algorithm, secret.as_bytes())
+        } else if let Some(path) = &self.private_key_path {
+            let path = base_dir.join(path);
+            let pem = std::fs::read(&path)?;
+            JwtKey::from_pem(&self.kid, self.algorithm, &pem)?
+        } else {
+            return Err(KeyRingError::MissingKeyMaterial(self.kid.to_owned()))
+        };
+        key.retire_at = self.retire_at;
+        Ok(key)
+    }
+}
+
+/// On-disk description of the key ring, edited by the `keys` command and loaded at startup.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct KeyRingManifest {
+    pub keys: Vec<KeyEntry>,
+}
+
+impl KeyRingManifest {
+    pub fn load(path: &Path) -> Result<Self, KeyRingError> {
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), KeyRingError> {
+        // Write to a temporary file first so a running instance never reads a partial manifest.
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Adds a key as verification-only, so every instance accepts it before it starts signing.
+    pub fn add(&mut self, mut entry: KeyEntry) -> Result<(), KeyRingError> {
+        if self.keys.iter().any(|key| key.kid == entry.kid) {
+            return Err(KeyRingError::DuplicateKey(entry.kid))
+        }
+        entry.status = match self.keys.is_empty() {
+            true => KeyStatus::Signing,
+            false => KeyStatus::Verification,
+        };
+        entry.retire_at = None;
+        self.keys.push(entry);
+        Ok(())
+    }
+
+    /// Makes `kid` the signing key. The previous signing key stays valid for verification
+    /// for `retire_after_seconds`, which must cover the restart of every instance, as they keep
+    /// signing with it until then, and the lifetime of the last tokens it signed.
+    pub fn promote(&mut self, kid: &str, retire_after_seconds: i64) -> Result<(), KeyRingError> {
+        if !self.keys.iter().any(|key| key.kid == kid) {
+            return Err(KeyRingError::UnknownKey(kid.to_owned()))
+        }
+
+        let retire_at = chrono::Utc::now().timestamp() + retire_after_seconds;
+        for key in self.keys.iter_mut() {
+            if key.kid == kid {
+                key.status = KeyStatus::Signing;
+                key.retire_at = None;
+            } else if key.status == KeyStatus::Signing {
+                key.status = KeyStatus::Verification;
+                key.retire_at = Some(retire_at);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the keys whose retirement time has passed, returning their IDs.
+    pub fn prune(&mut self, now: i64) -> Vec<String> {
+        let (retired, keys) = self.keys
+            .drain(..)
+            .partition(|key| key.retire_at.is_some_and(|retire_at| retire_at <= now));
+        self.keys = keys;
+        retired.into_iter().map(|key: KeyEntry| key.kid).collect()
+    }
+
+    pub fn key_ring(&self, base_dir: &Path) -> Result<JwtKeyRing, KeyRingError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut signing = None;
+        let mut verification = Vec::new();
+
+        for entry in &self.keys {
+            let key = entry.load(base_dir)?;
+            if key.is_retired(now) {
+                tracing::warn!("skipping retired JWT key: {}", entry.kid);
+                continue;
+            }
+            match entry.status {
+                KeyStatus::Signing if signing.is_some() => return Err(KeyRingError::MultipleSigningKeys),
+                KeyStatus::Signing => signing = Some(key),
+                KeyStatus::Verification => verification.push(key),
+            }
+        }
+
+        let signing = signing.ok_or(KeyRingError::NoSigningKey)?;
+        Ok(JwtKeyRing { signing, verification })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum KeyRingError {
+    #[error("key already exists: {0}")]
+    DuplicateKey(String),
+    #[error("unknown key: {0}")]
+    UnknownKey(String),
+    #[error("key ring has no signing key")]
+    NoSigningKey,
+    #[error("key ring has more than one signing key")]
+    MultipleSigningKeys,
+    #[error("key {0} has neither a private key path nor a secret")]
+    MissingKeyMaterial(String),
+    #[error("secret environment variable is not set: {0}")]
+    MissingSecret(String),
+    #[error(transparent)]
+    JwtKeyError(#[from] JwtKeyError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(kid: &str) -> KeyEntry {
+        KeyEntry {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::HS256,
+            private_key_path: None,
+            secret_env: Some(format!("{}_SECRET", kid.to_uppercase())),
+            status: KeyStatus::Signing,
+            retire_at: Some(0),
+        }
+    }
+
+    fn status(manifest: &KeyRingManifest, kid: &str) -> (KeyStatus, Option<i64>) {
+        let key = manifest.keys.iter().find(|key| key.kid == kid).unwrap();
+        (key.status, key.retire_at)
+    }
+
+    #[test]
+    fn only_the_first_key_added_signs() {
+        let mut manifest = KeyRingManifest::default();
+        manifest.add(entry("a")).unwrap();
+        manifest.add(entry("b")).unwrap();
+
+        assert_eq!(status(&manifest, "a"), (KeyStatus::Signing, None));
+        assert_eq!(status(&manifest, "b"), (KeyStatus::Verification, None));
+        assert!(matches!(manifest.add(entry("a")), Err(KeyRingError::DuplicateKey(_))));
+    }
+
+    #[test]
+    fn promote_retires_the_previous_signing_key_after_the_token_lifetime() {
+        let mut manifest = KeyRingManifest::default();
+        manifest.add(entry("a")).unwrap();
+        manifest.add(entry("b")).unwrap();
+
+        let before = chrono::Utc::now().timestamp();
+        manifest.promote("b", 3600).unwrap();
+        let after = chrono::Utc::now().timestamp();
+
+        assert_eq!(status(&manifest, "b"), (KeyStatus::Signing, None));
+        let (previous_status, retire_at) = status(&manifest, "a");
+        assert_eq!(previous_status, KeyStatus::Verification);
+        assert!((before + 3600..=after + 3600).contains(&retire_at.unwrap()));
+
+        assert!(matches!(manifest.promote("c", 3600), Err(KeyRingError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn promoting_a_retiring_key_back_keeps_it() {
+        let mut manifest = KeyRingManifest::default();
+        manifest.add(entry("a")).unwrap();
+        manifest.add(entry("b")).unwrap();
+        manifest.promote("b", 3600).unwrap();
+        manifest.promote("a", 3600).unwrap();
+
+        assert_eq!(status(&manifest, "a"), (KeyStatus::Signing, None));
+        assert_eq!(status(&manifest, "b").0, KeyStatus::Verification);
+        assert!(manifest.prune(chrono::Utc::now().timestamp()).is_empty());
+    }
+
+    #[test]
+    fn prune_removes_only_retired_keys() {
+        let mut manifest = KeyRingManifest::default();
+        manifest.add(entry("a")).unwrap();
+        manifest.add(entry("b")).unwrap();
+        manifest.add(entry("c")).unwrap();
+        manifest.promote("b", 100).unwrap();
+        let retire_at = status(&manifest, "a").1.unwrap();
+
+        assert!(manifest.prune(retire_at - 1).is_empty());
+        assert_eq!(manifest.prune(retire_at), ["a"]);
+
+        let kids: Vec<&str> = manifest.keys.iter().map(|key| key.kid.as_str()).collect();
+        assert_eq!(kids, ["b", "c"]);
+    }
+}
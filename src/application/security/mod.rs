@@ -1,4 +1,12 @@
 pub mod jwt;
+pub mod keyring;
 pub mod auth;
+pub mod cbor;
 pub mod validator;
-pub mod password;
\ No newline at end of file
+pub mod password;
+pub mod policy;
+pub mod oauth;
+pub mod role;
+pub mod secret;
+pub mod totp;
+pub mod webauthn;
\ No newline at end of file
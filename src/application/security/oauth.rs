@@ -0,0 +1,179 @@
+use axum::{
+    Json,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use crate::application::security::{auth::AuthError, secret};
+
+/// Errors of the OAuth endpoints, answered in the RFC 6749 format rather than as an `ApiError`.
+#[derive(Debug, Error)]
+pub enum OAuthError {
+    #[error("{0}")]
+    InvalidRequest(String),
+    #[error("client authentication failed")]
+    InvalidClient,
+    #[error("{0}")]
+    InvalidGrant(String),
+    #[error("the client is not allowed to use this grant type")]
+    UnauthorizedClient,
+    #[error("unsupported grant type")]
+    UnsupportedGrantType,
+    #[error("unsupported response type")]
+    UnsupportedResponseType,
+    #[error("{0}")]
+    InvalidScope(String),
+    #[error("the user has not approved the device yet")]
+    AuthorizationPending,
+    #[error("polling too fast, increase the interval")]
+    SlowDown,
+    #[error("the user denied the authorization request")]
+    AccessDenied,
+    #[error("the device code has expired")]
+    ExpiredToken,
+    #[error("internal server error")]
+    ServerError,
+}
+
+#[derive(Debug, Serialize)]
+struct OAuthErrorResponse {
+    error: &'static str,
+    error_description: String,
+}
+
+impl OAuthError {
+    /// Error code of RFC 6749 section 5.2, or RFC 8628 section 3.5 for the device flow.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant(_) => "invalid_grant",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::UnsupportedResponseType => "unsupported_response_type",
+            Self::InvalidScope(_) => "invalid_scope",
+            Self::AuthorizationPending => "authorization_pending",
+            Self::SlowDown => "slow_down",
+            Self::AccessDenied => "access_denied",
+            Self::ExpiredToken => "expired_token",
+            Self::ServerError => "server_error",
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidClient => StatusCode::UNAUTHORIZED,
+            Self::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let body = OAuthErrorResponse {
+            error: self.code(),
+            error_description: self.to_string(),
+        };
+        let no_store = (header::CACHE_CONTROL, "no-store");
+
+        match self {
+            Self::InvalidClient => {
+                let challenge = (header::WWW_AUTHENTICATE, "Basic realm=\"oauth\"");
+                (self.status_code(), [no_store, challenge], Json(body)).into_response()
+            }
+            _ => (self.status_code(), [no_store], Json(body)).into_response(),
+        }
+    }
+}
+
+impl From<AuthError> for OAuthError {
+    fn from(auth_error: AuthError) -> Self {
+        match auth_error {
+            AuthError::WrongCredentials
+            | AuthError::InvalidToken
+            | AuthError::InvalidBearerToken
+            | AuthError::RefreshTokenReused => Self::InvalidGrant(auth_error.to_string()),
+            _ => {
+                tracing::error!("oauth request failed: {}", auth_error);
+                Self::ServerError
+            }
+        }
+    }
+}
+
+impl From<sqlx::Error> for OAuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AuthError::from(e).into()
+    }
+}
+
+impl From<redis::RedisError> for OAuthError {
+    fn from(e: redis::RedisError) -> Self {
+        AuthError::from(e).into()
+    }
+}
+
+/// Checks a PKCE `S256` code verifier against the challenge sent to the authorization endpoint.
+pub fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    // RFC 7636 section 4.1: 43 to 128 unreserved characters.
+    let well_formed = (43..=128).contains(&code_verifier.len())
+        && code_verifier.bytes().all(|b| b.is_ascii_alphanumeric() || b"-._~".contains(&b));
+
+    let computed = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+    well_formed && secret::constant_time_eq(computed.as_bytes(), code_challenge.as_bytes())
+}
+
+/// Splits a space delimited `scope` parameter.
+pub fn parse_scope(scope: Option<&str>) -> Vec<String> {
+    scope.unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 7636 appendix B.
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    #[test]
+    fn accepts_the_rfc_7636_example() {
+        assert!(verify_pkce(VERIFIER, CHALLENGE));
+    }
+
+    #[test]
+    fn rejects_another_verifier() {
+        let other = VERIFIER.replace('d', "e");
+        assert!(!verify_pkce(&other, CHALLENGE));
+        assert!(!verify_pkce(VERIFIER, VERIFIER));
+    }
+
+    #[test]
+    fn rejects_malformed_verifiers() {
+        // The challenge matches, the verifier itself breaks RFC 7636 section 4.1.
+        let short = &VERIFIER[..42];
+        assert!(!verify_pkce(short, &BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(short.as_bytes()))));
+
+        let long = "a".repeat(129);
+        assert!(!verify_pkce(&long, &BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(long.as_bytes()))));
+
+        let invalid = format!("{}+", &VERIFIER[..42]);
+        assert!(!verify_pkce(&invalid, &BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(invalid.as_bytes()))));
+
+        let longest = "~".repeat(128);
+        assert!(verify_pkce(&longest, &BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(longest.as_bytes()))));
+    }
+
+    #[test]
+    fn splits_scopes_on_whitespace() {
+        assert_eq!(parse_scope(Some(" users:read  tokens:revoke ")), ["users:read", "tokens:revoke"]);
+        assert!(parse_scope(None).is_empty());
+    }
+}
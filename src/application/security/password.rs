@@ -11,24 +11,66 @@ use argon2::{
     Version,
     Params,
 };
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, mpsc::{SyncSender, TrySendError}};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use crate::application::config::Config;
 use crate::application::security::auth::AuthError;
 
+/// Argon2id cost parameters of newly hashed passwords.
+#[derive(Debug, Clone)]
+pub struct HashPolicy {
+    /// Memory cost, in KiB.
+    pub memory_kib: u32,
+    /// Time cost, in iterations.
+    pub iterations: u32,
+    /// Degree of parallelism, in lanes.
+    pub parallelism: u32,
+}
+
+impl HashPolicy {
+    pub fn params(&self) -> Result<Params, argon2::Error> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+/// Verifies `password` against a hash of any supported algorithm.
+///
+/// Besides argon2, bcrypt (`$2a$`, `$2b$`, `$2x$`, `$2y$`), scrypt and pbkdf2 hashes in the PHC string format are
+/// accepted, so accounts imported from older systems can still log in.
 pub fn compare(password: &str, hashed_password: &str) -> Result<bool, AuthError> {
     if password.is_empty() {
         return Err(AuthError::EmptyPassword)
     }
 
+    if is_bcrypt(hashed_password) {
+        // A malformed imported hash must fail as wrong credentials, not as a server error.
+        return Ok(bcrypt::verify(password, hashed_password).unwrap_or_else(|e| {
+            tracing::error!("invalid bcrypt hash: {}", e);
+            false
+        }))
+    }
+
     let parsed_hash = PasswordHash::new(hashed_password)
         .map_err(|_| AuthError::InvalidHashFormat)?;
 
-    let password_matched = Argon2::default()
+    // The cost parameters are read from the hash itself, not from the current policy.
+    let verifier: &dyn PasswordVerifier = match parsed_hash.algorithm.as_str() {
+        "argon2id" | "argon2i" | "argon2d" => &Argon2::default(),
+        "scrypt" => &Scrypt,
+        "pbkdf2-sha256" | "pbkdf2-sha512" => &Pbkdf2,
+        _ => return Err(AuthError::InvalidHashFormat),
+    };
+
+    let password_matched = verifier
         .verify_password(password.as_bytes(), &parsed_hash)
-        .map_or(false, |_| true);
+        .is_ok();
 
     Ok(password_matched)
 }
 
-pub fn hash(password: impl Into<String>) -> Result<String, AuthError> {
+pub fn hash(password: impl Into<String>, policy: &HashPolicy) -> Result<String, AuthError> {
     let password = password.into();
 
     if password.is_empty() {
@@ -40,12 +82,7 @@ pub fn hash(password: impl Into<String>) -> Result<String, AuthError> {
     let argon2 = Argon2::new(
         Algorithm::Argon2id,
         Version::V0x13,
-        Params::new(
-            15 * 1024, // Memory cost (in KiB) - reduced from default (e.g., 32 * 1024)
-            2,         // Time cost (iterations) - reduced from default (e.g., 3)
-            1,         // Parallelism (threads) - reduced from default (e.g., 4)
-            None,
-        ).map_err(|_| AuthError::HashingError)?
+        policy.params().map_err(|_| AuthError::HashingError)?,
     );
 
     let hashed_password = argon2
@@ -54,4 +91,232 @@ pub fn hash(password: impl Into<String>) -> Result<String, AuthError> {
         .to_string();
 
     Ok(hashed_password)
-}
\ No newline at end of file
+}
+
+/// Tells whether `hashed_password` uses another algorithm than argon2id or parameters weaker than `policy`.
+pub fn needs_rehash(hashed_password: &str, policy: &HashPolicy) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+        return true
+    };
+    if parsed_hash.algorithm != Algorithm::Argon2id.ident() || parsed_hash.version != Some(Version::V0x13.into()) {
+        return true
+    }
+    let Ok(params) = Params::try_from(&parsed_hash) else {
+        return true
+    };
+
+    params.m_cost() < policy.memory_kib || params.t_cost() < policy.iterations || params.p_cost() < policy.parallelism
+}
+
+fn is_bcrypt(hashed_password: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hashed_password.starts_with(prefix))
+}
+
+type HashingJob = Box<dyn FnOnce() + Send>;
+
+/// Runs hashing and verification on dedicated threads, keeping them off the async executor and off Tokio's shared
+/// blocking pool, where they would compete with file and DNS work.
+///
+/// `max_concurrency` threads hash at once and `queue_limit` more jobs wait for a free thread, further
+/// requests are turned away with [`AuthError::HashingOverloaded`] instead of piling up.
+#[derive(Debug)]
+pub struct HashingPool {
+    jobs: SyncSender<HashingJob>,
+    retry_after_seconds: u64,
+}
+
+impl HashingPool {
+    pub fn new(config: &Config) -> Self {
+        let (jobs, queue) = std::sync::mpsc::sync_channel::<HashingJob>(config.password_hash_queue_limit);
+        let queue = Arc::new(Mutex::new(queue));
+
+        for i in 0..config.password_hash_max_concurrency.max(1) {
+            let queue = Arc::clone(&queue);
+            let spawned = std::thread::Builder::new()
+                .name(format!("password-hash-{}", i))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job, the pool stops with its sender.
+                    let job = match queue.lock() {
+                        Ok(queue) => queue.recv(),
+                        Err(_) => return,
+                    };
+                    match job {
+                        // A panicking job must not take the thread down with it.
+                        Ok(job) => { let _ = std::panic::catch_unwind(AssertUnwindSafe(job)); }
+                        Err(_) => return,
+                    }
+                });
+
+            if let Err(e) = spawned {
+                tracing::error!("could not start password hashing thread: {}", e);
+                std::process::exit(1);
+            }
+        }
+
+        Self {
+            jobs,
+            retry_after_seconds: config.password_hash_retry_after_seconds,
+        }
+    }
+
+    /// [`compare`] on the pool.
+    pub async fn compare(&self, password: &str, hashed_password: &str) -> Result<bool, AuthError> {
+        let (password, hashed_password) = (password.to_owned(), hashed_password.to_owned());
+        self.run(move || compare(&password, &hashed_password)).await
+    }
+
+    /// [`hash`] on the pool.
+    pub async fn hash(&self, password: impl Into<String>, policy: &HashPolicy) -> Result<String, AuthError> {
+        let (password, policy) = (password.into(), policy.clone());
+        self.run(move || hash(password, &policy)).await
+    }
+
+    async fn run<T, F>(&self, job: F) -> Result<T, AuthError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, AuthError> + Send + 'static,
+    {
+        let (result_sender, result) = tokio::sync::oneshot::channel();
+        let job: HashingJob = Box::new(move || {
+            // The request may be gone by now, the result is dropped then.
+            let _ = result_sender.send(job());
+        });
+
+        self.jobs.try_send(job).map_err(|e| match e {
+            TrySendError::Full(_) => {
+                tracing::warn!("password hashing pool saturated, rejecting request");
+                AuthError::HashingOverloaded(self.retry_after_seconds)
+            }
+            TrySendError::Disconnected(_) => AuthError::HashingError,
+        })?;
+
+        // A job that panicked drops its sender without an answer.
+        result.await.map_err(|_| AuthError::HashingError)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> HashPolicy {
+        HashPolicy {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn verifies_argon2_hashes() {
+        let hashed_password = hash("correct horse", &policy()).unwrap();
+
+        assert!(compare("correct horse", &hashed_password).unwrap());
+        assert!(!compare("wrong horse", &hashed_password).unwrap());
+    }
+
+    #[test]
+    fn verifies_bcrypt_hashes() {
+        // Test vectors of OpenBSD bcrypt, as published with crypt_blowfish.
+        assert!(compare("U*U", "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW").unwrap());
+        assert!(compare("U*U*", "$2a$05$CCCCCCCCCCCCCCCCCCCCC.VGOzA784oUp/Z0DY336zx7pLYAy0lwK").unwrap());
+        assert!(!compare("U*U*", "$2a$05$CCCCCCCCCCCCCCCCCCCCC.E5YPO9kmyuRGyh0XouQYb4YMJKvyOeW").unwrap());
+
+        let hashed_2y = bcrypt::hash("correct horse", 4).unwrap().replacen("$2b$", "$2y$", 1);
+        assert!(compare("correct horse", &hashed_2y).unwrap());
+    }
+
+    #[test]
+    fn malformed_bcrypt_hash_is_a_mismatch() {
+        assert!(!compare("correct horse", "$2b$04$truncated").unwrap());
+    }
+
+    // The scrypt and pbkdf2 samples were produced by another implementation, Python's hashlib.
+    #[test]
+    fn verifies_scrypt_hashes() {
+        let hashed_password = "$scrypt$ln=4,r=8,p=1$c2FsdHNhbHRzYWx0c2FsdA$rkTI9fAgA7thDzzR8FHXhMiojYBreJBmYTkEzWypBCQ";
+
+        assert!(compare("correct horse", hashed_password).unwrap());
+        assert!(!compare("wrong horse", hashed_password).unwrap());
+    }
+
+    #[test]
+    fn verifies_pbkdf2_hashes() {
+        for hashed_password in [
+            "$pbkdf2-sha256$i=1000,l=32$c2FsdHNhbHRzYWx0c2FsdA$BBs+1+PaslLtBPULUr8/lQicvVuHiEPMz0i8MjLCbzM",
+            "$pbkdf2-sha512$i=1000,l=32$c2FsdHNhbHRzYWx0c2FsdA$EHLBej+uEvxlmr0QnHnceg1vM6h1wbwSP5XErh3Smiw",
+        ] {
+            assert!(compare("correct horse", hashed_password).unwrap());
+            assert!(!compare("wrong horse", hashed_password).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_empty_passwords_and_unknown_algorithms() {
+        let hashed_password = hash("correct horse", &policy()).unwrap();
+
+        assert!(matches!(compare("", &hashed_password), Err(AuthError::EmptyPassword)));
+        assert!(matches!(compare("correct horse", "$md5$abc"), Err(AuthError::InvalidHashFormat)));
+    }
+
+    #[test]
+    fn needs_rehash_for_other_algorithms_and_weaker_parameters() {
+        let current = hash("correct horse", &policy()).unwrap();
+        assert!(!needs_rehash(&current, &policy()));
+
+        let stronger = HashPolicy { memory_kib: 2048, ..policy() };
+        assert!(needs_rehash(&current, &stronger));
+        let more_iterations = HashPolicy { iterations: 2, ..policy() };
+        assert!(needs_rehash(&current, &more_iterations));
+
+        let weaker = HashPolicy { memory_kib: 512, ..policy() };
+        assert!(!needs_rehash(&current, &weaker));
+
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, policy().params().unwrap())
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        assert!(needs_rehash(&argon2i, &policy()));
+
+        assert!(needs_rehash(&bcrypt::hash("correct horse", 4).unwrap(), &policy()));
+        assert!(needs_rehash("not a hash", &policy()));
+    }
+
+    #[tokio::test]
+    async fn saturated_pool_answers_503_with_retry_after() {
+        let mut config = crate::application::config::test_config();
+        config.password_hash_max_concurrency = 1;
+        config.password_hash_queue_limit = 1;
+        config.password_hash_retry_after_seconds = 3;
+        let pool = Arc::new(HashingPool::new(&config));
+
+        // Hold the only thread busy, then fill the queue.
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let (started, running) = tokio::sync::oneshot::channel();
+        let busy = tokio::spawn({
+            let pool = Arc::clone(&pool);
+            async move {
+                pool.run(move || {
+                    let _ = started.send(());
+                    blocked.recv().map_err(|_| AuthError::HashingError)
+                }).await
+            }
+        });
+        running.await.unwrap();
+        let policy = policy();
+        let mut queued = std::pin::pin!(pool.hash("correct horse", &policy));
+        let waiting = tokio::time::timeout(std::time::Duration::from_millis(50), &mut queued).await;
+        assert!(waiting.is_err());
+
+        let error = pool.compare("correct horse", "$2b$04$truncated").await.unwrap_err();
+        assert!(matches!(error, AuthError::HashingOverloaded(3)));
+        let response = axum::response::IntoResponse::into_response(crate::api::ApiError::from(error));
+        assert_eq!(response.status(), axum::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[axum::http::header::RETRY_AFTER], "3");
+
+        release.send(()).unwrap();
+        busy.await.unwrap().unwrap();
+        assert!(compare("correct horse", &queued.await.unwrap()).unwrap());
+        assert!(!pool.compare("correct horse", "$2b$04$truncated").await.unwrap());
+    }
+}
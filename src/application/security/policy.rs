@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::application::security::{
+    auth::AuthError,
+    jwt::{AccessClaim, ClaimsMethods},
+};
+
+pub const USERS_READ: &str = "users:read";
+pub const USERS_DEACTIVATE: &str = "users:deactivate";
+pub const USERS_UNLOCK: &str = "users:unlock";
+pub const USERS_IMPERSONATE: &str = "users:impersonate";
+pub const TOKENS_REVOKE: &str = "tokens:revoke";
+pub const TOKENS_INTROSPECT: &str = "tokens:introspect";
+pub const OAUTH_CLIENTS_MANAGE: &str = "oauth_clients:manage";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrantScope {
+    /// The permission applies to every resource.
+    #[default]
+    Any,
+    /// The permission only applies to resources owned by the subject.
+    Own,
+}
+
+/// Permission granted to a role, either `"users:read"` or `{"permission": "users:read", "scope": "own"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Grant {
+    Permission(String),
+    Scoped {
+        permission: String,
+        #[serde(default)]
+        scope: GrantScope,
+    },
+}
+
+impl Grant {
+    fn permission(&self) -> &str {
+        match self {
+            Self::Permission(permission) | Self::Scoped { permission, .. } => permission,
+        }
+    }
+
+    fn scope(&self) -> GrantScope {
+        match self {
+            Self::Permission(_) => GrantScope::Any,
+            Self::Scoped { scope, .. } => *scope,
+        }
+    }
+
+    /// Matches `*`, `users:*` style wildcards as well as exact permissions.
+    fn matches(&self, permission: &str) -> bool {
+        let granted = self.permission();
+        granted == "*"
+            || granted == permission
+            || granted
+                .strip_suffix(":*")
+                .is_some_and(|prefix| permission.split(':').next() == Some(prefix))
+    }
+}
+
+/// Resource a permission is checked against.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Resource<'a> {
+    /// Owning user ID, `None` for collections and resources without an owner.
+    pub owner_id: Option<&'a str>,
+}
+
+impl<'a> Resource<'a> {
+    pub fn any() -> Self {
+        Self { owner_id: None }
+    }
+
+    pub fn owned_by(owner_id: &'a str) -> Self {
+        Self { owner_id: Some(owner_id) }
+    }
+}
+
+/// Maps roles to permissions, loaded from the JSON file at `POLICY_PATH`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Policy {
+    pub roles: HashMap<String, Vec<Grant>>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        let roles = HashMap::from([
+            ("admin".to_owned(), vec![Grant::Permission("*".to_owned())]),
+            ("user".to_owned(), vec![
+                Grant::Scoped { permission: USERS_READ.to_owned(), scope: GrantScope::Own },
+                Grant::Scoped { permission: TOKENS_REVOKE.to_owned(), scope: GrantScope::Own },
+            ]),
+        ]);
+        Self { roles }
+    }
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self, PolicyError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Tells whether the subject of `claims` may perform `permission` on `resource`.
+    ///
+    /// API keys only keep the permissions of their owner that are also within their scopes.
+    pub fn can(&self, claims: &AccessClaim, permission: &str, resource: &Resource) -> bool {
+        let is_owner = resource.owner_id.is_some_and(|owner_id| owner_id == claims.get_sub());
+
+        if let Some(scopes) = &claims.scopes
+            && !scopes.iter().any(|scope| Grant::Permission(scope.to_owned()).matches(permission))
+        {
+            return false
+        }
+
+        claims.roles
+            .iter()
+            .filter_map(|role| self.roles.get(&role.to_string()))
+            .flatten()
+            .filter(|grant| grant.matches(permission))
+            .any(|grant| match grant.scope() {
+                GrantScope::Any => true,
+                GrantScope::Own => is_owner,
+            })
+    }
+
+    pub fn authorize(&self, claims: &AccessClaim, permission: &str, resource: &Resource) -> Result<(), AuthError> {
+        if !self.can(claims, permission, resource) {
+            tracing::error!("access denied ({} on {:?}): {:#?}", permission, resource, claims);
+            return Err(AuthError::Forbidden)
+        }
+        Ok(())
+    }
+}
+
+/// Permission required by [`crate::api::extractor::RequirePermission`], implemented by marker types.
+pub trait RequiredPermission: Send + Sync + 'static {
+    const PERMISSION: &'static str;
+}
+
+pub struct UsersRead;
+
+impl RequiredPermission for UsersRead {
+    const PERMISSION: &'static str = USERS_READ;
+}
+
+pub struct UsersDeactivate;
+
+impl RequiredPermission for UsersDeactivate {
+    const PERMISSION: &'static str = USERS_DEACTIVATE;
+}
+
+pub struct UsersUnlock;
+
+impl RequiredPermission for UsersUnlock {
+    const PERMISSION: &'static str = USERS_UNLOCK;
+}
+
+pub struct UsersImpersonate;
+
+impl RequiredPermission for UsersImpersonate {
+    const PERMISSION: &'static str = USERS_IMPERSONATE;
+}
+
+pub struct TokensRevoke;
+
+impl RequiredPermission for TokensRevoke {
+    const PERMISSION: &'static str = TOKENS_REVOKE;
+}
+
+pub struct OAuthClientsManage;
+
+impl RequiredPermission for OAuthClientsManage {
+    const PERMISSION: &'static str = OAUTH_CLIENTS_MANAGE;
+}
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::security::role::Roles;
+
+    fn claims(sub: &str, roles: &str, scopes: Option<&[&str]>) -> AccessClaim {
+        AccessClaim {
+            sub: sub.to_owned(),
+            iss: String::new(),
+            aud: Vec::new(),
+            jti: String::new(),
+            iat: 0,
+            iat_ms: None,
+            exp: 0,
+            typ: 0,
+            roles: Roles::parse(roles),
+            fid: String::new(),
+            scopes: scopes.map(|scopes| scopes.iter().map(|scope| scope.to_string()).collect()),
+            cid: None,
+            act: None,
+        }
+    }
+
+    #[test]
+    fn admin_may_do_anything() {
+        let policy = Policy::default();
+        let admin = claims("1", "admin", None);
+
+        assert!(policy.can(&admin, USERS_DEACTIVATE, &Resource::any()));
+        assert!(policy.can(&admin, USERS_READ, &Resource::owned_by("2")));
+    }
+
+    #[test]
+    fn own_scope_requires_ownership() {
+        let policy = Policy::default();
+        let user = claims("1", "user", None);
+
+        assert!(policy.can(&user, USERS_READ, &Resource::owned_by("1")));
+        assert!(!policy.can(&user, USERS_READ, &Resource::owned_by("2")));
+        assert!(!policy.can(&user, USERS_READ, &Resource::any()));
+        assert!(!policy.can(&user, USERS_DEACTIVATE, &Resource::owned_by("1")));
+        assert!(matches!(policy.authorize(&user, USERS_READ, &Resource::any()), Err(AuthError::Forbidden)));
+    }
+
+    #[test]
+    fn unknown_roles_grant_nothing() {
+        let policy = Policy::default();
+        assert!(!policy.can(&claims("1", "auditor", None), USERS_READ, &Resource::owned_by("1")));
+        assert!(!policy.can(&claims("1", "", None), USERS_READ, &Resource::owned_by("1")));
+    }
+
+    #[test]
+    fn wildcards_match_a_whole_namespace_only() {
+        let policy: Policy = serde_json::from_str(r#"{"roles": {"support": ["users:*", {"permission": "tokens:revoke", "scope": "own"}]}}"#).unwrap();
+        let support = claims("1", "support", None);
+
+        assert!(policy.can(&support, USERS_READ, &Resource::any()));
+        assert!(policy.can(&support, USERS_UNLOCK, &Resource::owned_by("2")));
+        assert!(!policy.can(&support, OAUTH_CLIENTS_MANAGE, &Resource::any()));
+        assert!(!policy.can(&support, "usersx:read", &Resource::any()));
+        assert!(policy.can(&support, TOKENS_REVOKE, &Resource::owned_by("1")));
+        assert!(!policy.can(&support, TOKENS_REVOKE, &Resource::owned_by("2")));
+    }
+
+    #[test]
+    fn scopes_narrow_the_permissions_of_the_owner() {
+        let policy = Policy::default();
+        let read_only = claims("1", "admin", Some(&["users:read"]));
+
+        assert!(policy.can(&read_only, USERS_READ, &Resource::any()));
+        assert!(!policy.can(&read_only, USERS_DEACTIVATE, &Resource::any()));
+
+        // Scopes never grant more than the roles do.
+        let user = claims("1", "user", Some(&["*"]));
+        assert!(!policy.can(&user, USERS_DEACTIVATE, &Resource::any()));
+        assert!(!policy.can(&claims("1", "admin", Some(&[])), USERS_READ, &Resource::any()));
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    Admin,
+    User,
+    /// Role unknown to this service, kept so it survives a token round trip.
+    Other(String),
+}
+
+impl std::str::FromStr for Role {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" => Err(()),
+            "admin" => Ok(Self::Admin),
+            "user" => Ok(Self::User),
+            other => Ok(Self::Other(other.to_owned())),
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let role = match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+            Self::Other(role) => role,
+        };
+        write!(f, "{}", role)
+    }
+}
+
+/// Set of roles, stored as a comma separated string in the database and in tokens.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Roles(BTreeSet<Role>);
+
+impl Roles {
+    pub fn parse(roles: &str) -> Self {
+        Self(roles.split(',').filter_map(|role| role.parse().ok()).collect())
+    }
+
+    pub fn contains(&self, role: &Role) -> bool {
+        self.0.contains(role)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Role> {
+        self.0.iter()
+    }
+}
+
+impl Display for Roles {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let roles: Vec<String> = self.0.iter().map(Role::to_string).collect();
+        write!(f, "{}", roles.join(","))
+    }
+}
+
+impl Serialize for Roles {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Roles {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let roles = String::deserialize(deserializer)?;
+        Ok(Self::parse(&roles))
+    }
+}
+
+/// Role required by [`crate::api::extractor::RequireRole`], implemented by marker types.
+pub trait RequiredRole: Send + Sync + 'static {
+    const ROLE: Role;
+}
+
+pub struct Admin;
+
+impl RequiredRole for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+pub struct User;
+
+impl RequiredRole for User {
+    const ROLE: Role = Role::User;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_and_unknown_roles() {
+        let roles = Roles::parse(" Admin ,user,Auditor");
+
+        assert!(roles.contains(&Role::Admin));
+        assert!(roles.contains(&Role::User));
+        assert!(roles.contains(&Role::Other("auditor".to_owned())));
+        assert_eq!(roles.iter().count(), 3);
+    }
+
+    #[test]
+    fn skips_empty_and_duplicate_entries() {
+        assert_eq!(Roles::parse(""), Roles::default());
+        assert_eq!(Roles::parse("user,,USER, "), Roles::parse("user"));
+    }
+
+    #[test]
+    fn round_trips_through_a_token() {
+        let roles = Roles::parse("user,admin,auditor");
+        let json = serde_json::to_string(&roles).unwrap();
+
+        assert_eq!(json, r#""admin,user,auditor""#);
+        assert_eq!(serde_json::from_str::<Roles>(&json).unwrap(), roles);
+    }
+}
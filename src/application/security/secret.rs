@@ -0,0 +1,43 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
+
+/// Generates a random URL-safe token carrying `bytes` bytes of entropy.
+pub fn generate_token(bytes: usize) -> String {
+    let mut buffer = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut buffer);
+    BASE64_URL_SAFE_NO_PAD.encode(buffer)
+}
+
+/// Hashes a high-entropy token for storage, a fast hash is enough since it cannot be brute-forced.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Compares secrets in a time that does not depend on where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generates a short code meant to be typed by a user, such as `WDJB-MJHT`.
+///
+/// Vowels and look-alike characters are left out, see RFC 8628 section 6.1.
+pub fn generate_user_code() -> String {
+    const CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+    let code: String = (0..8)
+        .map(|_| CHARSET[OsRng.next_u32() as usize % CHARSET.len()] as char)
+        .collect();
+    format!("{}-{}", &code[..4], &code[4..])
+}
+
+/// Normalizes a user code typed by a user, ignoring case and separators.
+pub fn normalize_user_code(user_code: &str) -> String {
+    let code: String = user_code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    match code.len() {
+        8 => format!("{}-{}", &code[..4], &code[4..]),
+        _ => code,
+    }
+}
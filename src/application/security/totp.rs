@@ -0,0 +1,105 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use ring::hmac;
+use crate::application::security::secret;
+
+/// Length of the time step in seconds, as expected by authenticator apps.
+pub const TOTP_STEP_SECONDS: u64 = 30;
+pub const TOTP_DIGITS: u32 = 6;
+
+/// Generates a random 160 bits secret, base32 encoded as in the `otpauth://` URI.
+pub fn generate_secret() -> String {
+    let mut buffer = [0u8; 20];
+    OsRng.fill_bytes(&mut buffer);
+    BASE32_NOPAD.encode(&buffer)
+}
+
+/// Builds the provisioning URI rendered as a QR code by authenticator apps.
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    let account = utf8_percent_encode(account, NON_ALPHANUMERIC);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer, account, secret, issuer, TOTP_DIGITS, TOTP_STEP_SECONDS,
+    )
+}
+
+/// Computes the RFC 6238 code of a time step.
+pub fn generate(secret: &str, step: u64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &key);
+    let digest = hmac::sign(&key, &step.to_be_bytes());
+    let digest = digest.as_ref();
+
+    // Dynamic truncation, see RFC 4226 section 5.3.
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([digest[offset] & 0x7f, digest[offset + 1], digest[offset + 2], digest[offset + 3]]);
+    let code = binary % 10u32.pow(TOTP_DIGITS);
+
+    Some(format!("{:0width$}", code, width = TOTP_DIGITS as usize))
+}
+
+/// Checks `code` against the current time step and `skew` steps on each side.
+///
+/// Returns the matching step so callers can refuse a code that was already used.
+pub fn verify(secret: &str, code: &str, now: u64, skew: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != TOTP_DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None
+    }
+
+    let current = now / TOTP_STEP_SECONDS;
+    (current.saturating_sub(skew)..=current + skew)
+        .find(|step| generate(secret, *step).is_some_and(|expected| secret::constant_time_eq(expected.as_bytes(), code.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ASCII `12345678901234567890`, the SHA-1 seed of RFC 6238 appendix B.
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn generates_the_rfc_6238_codes() {
+        // The RFC lists 8 digit codes, 6 digit codes are their last 6 digits.
+        for (time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ] {
+            assert_eq!(generate(SECRET, time / TOTP_STEP_SECONDS).as_deref(), Some(code), "time {}", time);
+        }
+    }
+
+    #[test]
+    fn verifies_within_the_skew_and_returns_the_step() {
+        let step = 1111111109 / TOTP_STEP_SECONDS;
+
+        assert_eq!(verify(SECRET, "081804", 1111111109, 0), Some(step));
+        assert_eq!(verify(SECRET, " 081804 ", 1111111109, 0), Some(step));
+        assert_eq!(verify(SECRET, "081804", 1111111109 + TOTP_STEP_SECONDS, 1), Some(step));
+        assert_eq!(verify(SECRET, "081804", 1111111109 - TOTP_STEP_SECONDS, 1), Some(step));
+        assert_eq!(verify(SECRET, "081804", 1111111109 + 2 * TOTP_STEP_SECONDS, 1), None);
+    }
+
+    #[test]
+    fn rejects_malformed_codes_and_secrets() {
+        assert_eq!(verify(SECRET, "81804", 1111111109, 1), None);
+        assert_eq!(verify(SECRET, "0818040", 1111111109, 1), None);
+        assert_eq!(verify(SECRET, "08180a", 1111111109, 1), None);
+        assert_eq!(verify("not base32!", "081804", 1111111109, 1), None);
+    }
+
+    #[test]
+    fn generated_secrets_decode_to_160_bits() {
+        let secret = generate_secret();
+        assert_eq!(BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(), 20);
+        assert!(provisioning_uri("My App", "alice@example.com", &secret)
+            .starts_with("otpauth://totp/My%20App:alice%40example%2Ecom?secret="));
+    }
+}
@@ -38,10 +38,41 @@ pub fn validate_identifier(identifier: &str) -> Result<(), ValidationError> {
     Ok(())
 }
 
+pub fn validate_username(username: &str) -> Result<(), ValidationError> {
+    // Usernames share the login identifier with emails, they must not be mistaken for one.
+    if username.contains("@") {
+        let mut err = ValidationError::new("invalid_username");
+        err.message = Some("username cannot contain @".into());
+        return Err(err);
+    }
+    validate_identifier(username)
+}
+
+pub fn validate_email_address(email: &str) -> Result<(), ValidationError> {
+    if !validate_email(email) {
+        let mut err = ValidationError::new("invalid_email_format");
+        err.message = Some("invalid email format".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
 pub fn validate_email(email: &str) -> bool {
     let email_regex = Regex::new(
         r"^[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+\.[a-zA-Z0-9-.]+$"
     ).unwrap();
 
     email_regex.is_match(email)
+}
+
+pub fn validate_scopes(scopes: &[String]) -> Result<(), ValidationError> {
+    // Same syntax as the permissions of the policy: `*`, `users:*` or `users:read`.
+    let scope_regex = Regex::new(r"^(\*|[a-z_]+:(\*|[a-z_]+))$").unwrap();
+
+    if let Some(scope) = scopes.iter().find(|scope| !scope_regex.is_match(scope)) {
+        let mut err = ValidationError::new("invalid_scope");
+        err.message = Some(format!("invalid scope: {}", scope).into());
+        return Err(err);
+    }
+    Ok(())
 }
\ No newline at end of file
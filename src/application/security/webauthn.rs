@@ -0,0 +1,541 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use crate::application::security::cbor::{self, CborError, Value};
+
+/// COSE algorithm identifiers offered to authenticators, in order of preference.
+pub const COSE_ALG_ES256: i64 = -7;
+pub const COSE_ALG_EDDSA: i64 = -8;
+pub const COSE_ALG_RS256: i64 = -257;
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [COSE_ALG_ES256, COSE_ALG_EDDSA, COSE_ALG_RS256];
+
+/// Base64url as used by the WebAuthn JSON serialization, padding is tolerated on input.
+pub const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+#[derive(Debug, Error)]
+pub enum WebAuthnError {
+    #[error("invalid client data: {0}")]
+    InvalidClientData(&'static str),
+    #[error("invalid authenticator data: {0}")]
+    InvalidAuthenticatorData(&'static str),
+    #[error("invalid attestation object")]
+    InvalidAttestationObject,
+    #[error("unsupported public key")]
+    UnsupportedPublicKey,
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error(transparent)]
+    Cbor(#[from] CborError),
+}
+
+/// Relying party a ceremony is checked against.
+#[derive(Debug)]
+pub struct RelyingParty<'a> {
+    pub id: &'a str,
+    pub origin: &'a str,
+    pub require_user_verification: bool,
+}
+
+/// `CollectedClientData` signed over by the authenticator, see WebAuthn Level 2 section 5.8.1.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub challenge: String,
+    pub origin: String,
+    #[serde(default)]
+    pub cross_origin: bool,
+}
+
+impl ClientData {
+    pub fn parse(client_data_json: &[u8]) -> Result<Self, WebAuthnError> {
+        serde_json::from_slice(client_data_json).map_err(|_| WebAuthnError::InvalidClientData("malformed JSON"))
+    }
+
+    fn check(&self, typ: &str, challenge: &str, rp: &RelyingParty) -> Result<(), WebAuthnError> {
+        if self.typ != typ {
+            return Err(WebAuthnError::InvalidClientData("unexpected ceremony type"))
+        }
+        if self.challenge != challenge {
+            return Err(WebAuthnError::InvalidClientData("challenge mismatch"))
+        }
+        if self.origin != rp.origin || self.cross_origin {
+            return Err(WebAuthnError::InvalidClientData("origin mismatch"))
+        }
+        Ok(())
+    }
+}
+
+/// Authenticator data, see WebAuthn Level 2 section 6.1.
+#[derive(Debug)]
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+/// Credential created by a registration ceremony.
+#[derive(Debug)]
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    /// Public key in its COSE_Key form, as stored.
+    pub public_key: Vec<u8>,
+}
+
+impl AuthenticatorData {
+    pub fn parse(data: &[u8]) -> Result<Self, WebAuthnError> {
+        if data.len() < 37 {
+            return Err(WebAuthnError::InvalidAuthenticatorData("too short"))
+        }
+        let mut rp_id_hash = [0u8; 32];
+        rp_id_hash.copy_from_slice(&data[..32]);
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // AAGUID (16 bytes), credential ID length (2 bytes), credential ID, then the COSE key.
+            let id_len = data.get(53..55)
+                .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+                .ok_or(WebAuthnError::InvalidAuthenticatorData("truncated attested credential"))?;
+            let credential_id = data.get(55..55 + id_len)
+                .ok_or(WebAuthnError::InvalidAuthenticatorData("truncated credential ID"))?;
+            let key_bytes = &data[55 + id_len..];
+            let (_, key_len) = cbor::decode(key_bytes)?;
+
+            Some(AttestedCredential {
+                credential_id: credential_id.to_vec(),
+                public_key: key_bytes[..key_len].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    fn check(&self, rp: &RelyingParty) -> Result<(), WebAuthnError> {
+        if self.rp_id_hash[..] != Sha256::digest(rp.id.as_bytes())[..] {
+            return Err(WebAuthnError::InvalidAuthenticatorData("relying party ID mismatch"))
+        }
+        if self.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebAuthnError::InvalidAuthenticatorData("user not present"))
+        }
+        if rp.require_user_verification && self.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(WebAuthnError::InvalidAuthenticatorData("user not verified"))
+        }
+        Ok(())
+    }
+}
+
+/// Public key of a credential, decoded from its COSE_Key form (RFC 9053).
+#[derive(Debug)]
+pub enum CoseKey {
+    Es256 { point: Vec<u8> },
+    EdDsa { x: Vec<u8> },
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+    pub fn parse(cose_key: &[u8]) -> Result<Self, WebAuthnError> {
+        let (key, _) = cbor::decode(cose_key)?;
+        let param = |label: i128| key.get(&Value::Integer(label));
+        let bytes = |label: i128| param(label).and_then(Value::as_bytes).ok_or(WebAuthnError::UnsupportedPublicKey);
+
+        let kty = param(1).and_then(Value::as_integer);
+        let alg = param(3).and_then(Value::as_integer).map(|alg| alg as i64);
+        let crv = param(-1).and_then(Value::as_integer);
+
+        match (kty, alg) {
+            // EC2 key on P-256, stored as an uncompressed point.
+            (Some(2), Some(COSE_ALG_ES256)) if crv == Some(1) => {
+                let (x, y) = (bytes(-2)?, bytes(-3)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(WebAuthnError::UnsupportedPublicKey)
+                }
+                Ok(Self::Es256 { point: [&[0x04], x, y].concat() })
+            }
+            // OKP key on Ed25519.
+            (Some(1), Some(COSE_ALG_EDDSA)) if crv == Some(6) => Ok(Self::EdDsa { x: bytes(-2)?.to_vec() }),
+            (Some(3), Some(COSE_ALG_RS256)) => Ok(Self::Rs256 { n: bytes(-1)?.to_vec(), e: bytes(-2)?.to_vec() }),
+            _ => Err(WebAuthnError::UnsupportedPublicKey),
+        }
+    }
+
+    pub fn algorithm(&self) -> i64 {
+        match self {
+            Self::Es256 { .. } => COSE_ALG_ES256,
+            Self::EdDsa { .. } => COSE_ALG_EDDSA,
+            Self::Rs256 { .. } => COSE_ALG_RS256,
+        }
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), WebAuthnError> {
+        let verified = match self {
+            Self::Es256 { point } => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point).verify(message, signature),
+            Self::EdDsa { x } => UnparsedPublicKey::new(&signature::ED25519, x).verify(message, signature),
+            Self::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature),
+        };
+        verified.map_err(|_| WebAuthnError::InvalidSignature)
+    }
+}
+
+/// Outcome of a successful registration ceremony.
+#[derive(Debug)]
+pub struct VerifiedRegistration {
+    pub credential: AttestedCredential,
+    pub algorithm: i64,
+    pub sign_count: u32,
+}
+
+/// Verifies the response of `navigator.credentials.create()` to the options carrying `challenge`.
+///
+/// Attestation is requested as `none`, so attestation statements are not verified: the provenance of the
+/// authenticator is not relied upon, only the possession of the new key.
+pub fn verify_registration(
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+    challenge: &str,
+    rp: &RelyingParty,
+) -> Result<VerifiedRegistration, WebAuthnError> {
+    ClientData::parse(client_data_json)?.check("webauthn.create", challenge, rp)?;
+
+    let (attestation, _) = cbor::decode(attestation_object)?;
+    let auth_data = attestation.get(&Value::Text("authData".to_owned()))
+        .and_then(Value::as_bytes)
+        .ok_or(WebAuthnError::InvalidAttestationObject)?;
+    attestation.get(&Value::Text("fmt".to_owned()))
+        .and_then(Value::as_text)
+        .ok_or(WebAuthnError::InvalidAttestationObject)?;
+
+    let auth_data = AuthenticatorData::parse(auth_data)?;
+    auth_data.check(rp)?;
+
+    let credential = auth_data.attested_credential
+        .ok_or(WebAuthnError::InvalidAuthenticatorData("missing attested credential"))?;
+    let algorithm = CoseKey::parse(&credential.public_key)?.algorithm();
+
+    Ok(VerifiedRegistration {
+        credential,
+        algorithm,
+        sign_count: auth_data.sign_count,
+    })
+}
+
+/// Verifies the response of `navigator.credentials.get()` with the stored COSE `public_key` of the credential,
+/// returning the new signature counter.
+pub fn verify_assertion(
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+    challenge: &str,
+    rp: &RelyingParty,
+) -> Result<u32, WebAuthnError> {
+    ClientData::parse(client_data_json)?.check("webauthn.get", challenge, rp)?;
+
+    let auth_data = AuthenticatorData::parse(authenticator_data)?;
+    auth_data.check(rp)?;
+
+    let message = [authenticator_data, &Sha256::digest(client_data_json)].concat();
+    CoseKey::parse(public_key)?.verify(&message, signature)?;
+
+    Ok(auth_data.sign_count)
+}
+
+/// Authenticator backed by a software key, driving the ceremonies of tests.
+#[cfg(test)]
+pub(crate) struct SoftAuthenticator {
+    key: SoftKey,
+    pub credential_id: Vec<u8>,
+    pub rp_id: String,
+    pub origin: String,
+    pub flags: u8,
+    pub sign_count: u32,
+}
+
+#[cfg(test)]
+enum SoftKey {
+    Es256(signature::EcdsaKeyPair),
+    EdDsa(signature::Ed25519KeyPair),
+}
+
+#[cfg(test)]
+impl SoftAuthenticator {
+    pub fn es256(rp_id: &str, origin: &str) -> Self {
+        let rng = ring::rand::SystemRandom::new();
+        let alg = &signature::ECDSA_P256_SHA256_ASN1_SIGNING;
+        let pkcs8 = signature::EcdsaKeyPair::generate_pkcs8(alg, &rng).unwrap();
+        let key = signature::EcdsaKeyPair::from_pkcs8(alg, pkcs8.as_ref(), &rng).unwrap();
+        Self::new(SoftKey::Es256(key), rp_id, origin)
+    }
+
+    pub fn ed25519(rp_id: &str, origin: &str) -> Self {
+        let pkcs8 = signature::Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new()).unwrap();
+        let key = signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        Self::new(SoftKey::EdDsa(key), rp_id, origin)
+    }
+
+    fn new(key: SoftKey, rp_id: &str, origin: &str) -> Self {
+        Self {
+            key,
+            credential_id: crate::application::security::secret::generate_token(16).into_bytes(),
+            rp_id: rp_id.to_owned(),
+            origin: origin.to_owned(),
+            flags: FLAG_USER_PRESENT | FLAG_USER_VERIFIED,
+            sign_count: 0,
+        }
+    }
+
+    /// COSE_Key form of the public key.
+    pub fn cose_key(&self) -> Vec<u8> {
+        use ring::signature::KeyPair;
+        match &self.key {
+            SoftKey::Es256(key) => {
+                let point = key.public_key().as_ref();
+                let (x, y) = (&point[1..33], &point[33..]);
+                [cbor_head(5, 5), cbor_int(1), cbor_int(2), cbor_int(3), cbor_int(COSE_ALG_ES256), cbor_int(-1), cbor_int(1),
+                    cbor_int(-2), cbor_bytes(x), cbor_int(-3), cbor_bytes(y)].concat()
+            }
+            SoftKey::EdDsa(key) => {
+                [cbor_head(5, 4), cbor_int(1), cbor_int(1), cbor_int(3), cbor_int(COSE_ALG_EDDSA), cbor_int(-1), cbor_int(6),
+                    cbor_int(-2), cbor_bytes(key.public_key().as_ref())].concat()
+            }
+        }
+    }
+
+    pub fn client_data(&self, typ: &str, challenge: &str) -> Vec<u8> {
+        serde_json::json!({ "type": typ, "challenge": challenge, "origin": self.origin }).to_string().into_bytes()
+    }
+
+    pub fn authenticator_data(&self, attested: bool) -> Vec<u8> {
+        let flags = if attested { self.flags | FLAG_ATTESTED_CREDENTIAL } else { self.flags };
+        let mut data = [&Sha256::digest(self.rp_id.as_bytes())[..], &[flags], &self.sign_count.to_be_bytes()].concat();
+        if attested {
+            data.extend_from_slice(&[0u8; 16]);
+            data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(&self.credential_id);
+            data.extend_from_slice(&self.cose_key());
+        }
+        data
+    }
+
+    /// Answers `navigator.credentials.create()`, returning the client data and the attestation object.
+    pub fn register(&self, challenge: &str) -> (Vec<u8>, Vec<u8>) {
+        let attestation_object = [
+            cbor_head(5, 3),
+            cbor_text("fmt"), cbor_text("none"),
+            cbor_text("attStmt"), cbor_head(5, 0),
+            cbor_text("authData"), cbor_bytes(&self.authenticator_data(true)),
+        ].concat();
+        (self.client_data("webauthn.create", challenge), attestation_object)
+    }
+
+    /// Answers `navigator.credentials.get()` after bumping the counter, returning the client data,
+    /// the authenticator data and the signature.
+    pub fn sign(&mut self, challenge: &str) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        self.sign_count += 1;
+        let client_data_json = self.client_data("webauthn.get", challenge);
+        let authenticator_data = self.authenticator_data(false);
+        let message = [&authenticator_data[..], &Sha256::digest(&client_data_json)].concat();
+        let signature = match &self.key {
+            SoftKey::Es256(key) => key.sign(&ring::rand::SystemRandom::new(), &message).unwrap().as_ref().to_vec(),
+            SoftKey::EdDsa(key) => key.sign(&message).as_ref().to_vec(),
+        };
+        (client_data_json, authenticator_data, signature)
+    }
+}
+
+#[cfg(test)]
+fn cbor_head(major: u8, len: usize) -> Vec<u8> {
+    match len {
+        0..=23 => vec![major << 5 | len as u8],
+        24..=255 => vec![major << 5 | 24, len as u8],
+        _ => [&[major << 5 | 25][..], &(len as u16).to_be_bytes()].concat(),
+    }
+}
+
+#[cfg(test)]
+fn cbor_int(value: i64) -> Vec<u8> {
+    if value < 0 { cbor_head(1, (-1 - value) as usize) } else { cbor_head(0, value as usize) }
+}
+
+#[cfg(test)]
+fn cbor_bytes(value: &[u8]) -> Vec<u8> {
+    [cbor_head(2, value.len()), value.to_vec()].concat()
+}
+
+#[cfg(test)]
+fn cbor_text(value: &str) -> Vec<u8> {
+    [cbor_head(3, value.len()), value.as_bytes().to_vec()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RP: RelyingParty = RelyingParty {
+        id: "localhost",
+        origin: "http://localhost:8080",
+        require_user_verification: true,
+    };
+
+    fn authenticators() -> [SoftAuthenticator; 2] {
+        [SoftAuthenticator::es256(RP.id, RP.origin), SoftAuthenticator::ed25519(RP.id, RP.origin)]
+    }
+
+    #[test]
+    fn registers_and_signs_in() {
+        for mut authenticator in authenticators() {
+            let (client_data_json, attestation_object) = authenticator.register("challenge");
+            let registration = verify_registration(&client_data_json, &attestation_object, "challenge", &RP).unwrap();
+
+            assert_eq!(registration.credential.credential_id, authenticator.credential_id);
+            assert_eq!(registration.credential.public_key, authenticator.cose_key());
+            assert_eq!(registration.sign_count, 0);
+
+            let (client_data_json, authenticator_data, signature) = authenticator.sign("login");
+            let sign_count = verify_assertion(&client_data_json, &authenticator_data, &signature,
+                &registration.credential.public_key, "login", &RP).unwrap();
+            assert_eq!(sign_count, 1);
+        }
+    }
+
+    #[test]
+    fn rejects_another_challenge_or_ceremony() {
+        for mut authenticator in authenticators() {
+            let (client_data_json, attestation_object) = authenticator.register("challenge");
+            assert!(matches!(verify_registration(&client_data_json, &attestation_object, "other", &RP),
+                Err(WebAuthnError::InvalidClientData("challenge mismatch"))));
+
+            let (client_data_json, authenticator_data, signature) = authenticator.sign("challenge");
+            assert!(matches!(verify_registration(&client_data_json, &attestation_object, "challenge", &RP),
+                Err(WebAuthnError::InvalidClientData("unexpected ceremony type"))));
+            assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &authenticator.cose_key(), "other", &RP),
+                Err(WebAuthnError::InvalidClientData("challenge mismatch"))));
+        }
+    }
+
+    #[test]
+    fn rejects_another_origin_or_relying_party() {
+        for mut authenticator in authenticators() {
+            authenticator.origin = "https://evil.example".to_owned();
+            let (client_data_json, attestation_object) = authenticator.register("challenge");
+            assert!(matches!(verify_registration(&client_data_json, &attestation_object, "challenge", &RP),
+                Err(WebAuthnError::InvalidClientData("origin mismatch"))));
+
+            authenticator.origin = RP.origin.to_owned();
+            authenticator.rp_id = "evil.example".to_owned();
+            let (client_data_json, attestation_object) = authenticator.register("challenge");
+            assert!(matches!(verify_registration(&client_data_json, &attestation_object, "challenge", &RP),
+                Err(WebAuthnError::InvalidAuthenticatorData("relying party ID mismatch"))));
+
+            let (client_data_json, authenticator_data, signature) = authenticator.sign("challenge");
+            assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &authenticator.cose_key(), "challenge", &RP),
+                Err(WebAuthnError::InvalidAuthenticatorData("relying party ID mismatch"))));
+        }
+    }
+
+    #[test]
+    fn requires_user_verification_when_configured() {
+        for mut authenticator in authenticators() {
+            authenticator.flags = FLAG_USER_PRESENT;
+            let (client_data_json, authenticator_data, signature) = authenticator.sign("challenge");
+            let public_key = authenticator.cose_key();
+
+            assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &public_key, "challenge", &RP),
+                Err(WebAuthnError::InvalidAuthenticatorData("user not verified"))));
+
+            let rp = RelyingParty { require_user_verification: false, ..RP };
+            assert!(verify_assertion(&client_data_json, &authenticator_data, &signature, &public_key, "challenge", &rp).is_ok());
+
+            authenticator.flags = 0;
+            let (client_data_json, authenticator_data, signature) = authenticator.sign("challenge");
+            assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &public_key, "challenge", &rp),
+                Err(WebAuthnError::InvalidAuthenticatorData("user not present"))));
+        }
+    }
+
+    #[test]
+    fn rejects_a_signature_of_another_key_or_message() {
+        let [mut es256, mut ed25519] = authenticators();
+        let (client_data_json, authenticator_data, signature) = es256.sign("challenge");
+
+        assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &ed25519.cose_key(), "challenge", &RP),
+            Err(WebAuthnError::InvalidSignature)));
+
+        let mut tampered = authenticator_data.clone();
+        tampered[36] ^= 1;
+        assert!(matches!(verify_assertion(&client_data_json, &tampered, &signature, &es256.cose_key(), "challenge", &RP),
+            Err(WebAuthnError::InvalidSignature)));
+
+        let (client_data_json, authenticator_data, signature) = ed25519.sign("challenge");
+        assert!(matches!(verify_assertion(&client_data_json, &authenticator_data, &signature, &SoftAuthenticator::ed25519(RP.id, RP.origin).cose_key(), "challenge", &RP),
+            Err(WebAuthnError::InvalidSignature)));
+    }
+
+    #[test]
+    fn parses_supported_cose_keys() {
+        let [es256, ed25519] = authenticators();
+
+        assert!(matches!(CoseKey::parse(&es256.cose_key()), Ok(CoseKey::Es256 { point }) if point.len() == 65 && point[0] == 0x04));
+        assert!(matches!(CoseKey::parse(&ed25519.cose_key()), Ok(CoseKey::EdDsa { x }) if x.len() == 32));
+
+        let rsa = [cbor_head(5, 4), cbor_int(1), cbor_int(3), cbor_int(3), cbor_int(COSE_ALG_RS256),
+            cbor_int(-1), cbor_bytes(&[0xc5; 256]), cbor_int(-2), cbor_bytes(&[0x01, 0x00, 0x01])].concat();
+        assert!(matches!(CoseKey::parse(&rsa), Ok(CoseKey::Rs256 { n, e }) if n.len() == 256 && e == [1, 0, 1]));
+    }
+
+    #[test]
+    fn rejects_unsupported_cose_keys() {
+        let x = [0x11; 32];
+        let ec2 = |alg: i64, crv: i64, y: &[u8]| [cbor_head(5, 5), cbor_int(1), cbor_int(2), cbor_int(3), cbor_int(alg),
+            cbor_int(-1), cbor_int(crv), cbor_int(-2), cbor_bytes(&x), cbor_int(-3), cbor_bytes(y)].concat();
+
+        // P-384 curve, ES384 algorithm, truncated coordinate.
+        for key in [ec2(COSE_ALG_ES256, 2, &x), ec2(-35, 1, &x), ec2(COSE_ALG_ES256, 1, &x[..31])] {
+            assert!(matches!(CoseKey::parse(&key), Err(WebAuthnError::UnsupportedPublicKey)));
+        }
+
+        // Ed448 curve, missing public key.
+        let okp = [cbor_head(5, 4), cbor_int(1), cbor_int(1), cbor_int(3), cbor_int(COSE_ALG_EDDSA),
+            cbor_int(-1), cbor_int(7), cbor_int(-2), cbor_bytes(&x)].concat();
+        assert!(matches!(CoseKey::parse(&okp), Err(WebAuthnError::UnsupportedPublicKey)));
+        let okp = [cbor_head(5, 3), cbor_int(1), cbor_int(1), cbor_int(3), cbor_int(COSE_ALG_EDDSA), cbor_int(-1), cbor_int(6)].concat();
+        assert!(matches!(CoseKey::parse(&okp), Err(WebAuthnError::UnsupportedPublicKey)));
+
+        assert!(matches!(CoseKey::parse(&cbor_bytes(&x)), Err(WebAuthnError::UnsupportedPublicKey)));
+        assert!(matches!(CoseKey::parse(&[0xa5, 0x01]), Err(WebAuthnError::Cbor(CborError::UnexpectedEnd))));
+    }
+
+    #[test]
+    fn rejects_truncated_authenticator_data() {
+        let authenticator = SoftAuthenticator::es256(RP.id, RP.origin);
+        let data = authenticator.authenticator_data(true);
+
+        assert!(matches!(AuthenticatorData::parse(&data[..36]), Err(WebAuthnError::InvalidAuthenticatorData("too short"))));
+        assert!(matches!(AuthenticatorData::parse(&data[..54]), Err(WebAuthnError::InvalidAuthenticatorData("truncated attested credential"))));
+        assert!(matches!(AuthenticatorData::parse(&data[..60]), Err(WebAuthnError::InvalidAuthenticatorData("truncated credential ID"))));
+        assert!(matches!(AuthenticatorData::parse(&data[..data.len() - 1]), Err(WebAuthnError::Cbor(CborError::UnexpectedEnd))));
+    }
+}
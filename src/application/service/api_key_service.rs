@@ -0,0 +1,119 @@
+use serde::Serialize;
+use uuid::Uuid;
+use crate::application::{
+    constant::API_KEY_PREFIX,
+    repository::{api_key_repository::ApiKeyRepositoryExt, user_repository::UserRepositoryExt},
+    security::{
+        auth::AuthError,
+        jwt::{AccessClaim, JwtTokenType},
+        role::Roles,
+        secret,
+    },
+    service::token_service,
+    state::SharedState,
+};
+use crate::domain::entities::api_key::{ApiKey, NewApiKey};
+
+/// Number of characters of the key kept in clear to tell keys apart.
+const API_KEY_DISPLAY_PREFIX_LEN: usize = 12;
+/// Precision of the last use time of a key, a key used more often is recorded at most once per interval.
+const API_KEY_TOUCH_INTERVAL_SECONDS: i64 = 60;
+
+/// A newly created API key, the only time the key itself is returned.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+pub async fn create_api_key(
+    user_id: Uuid,
+    name: &str,
+    scopes: &[String],
+    expires_in_days: Option<i64>,
+    state: &SharedState,
+) -> Result<CreatedApiKey, AuthError> {
+    let key = format!("{}{}", API_KEY_PREFIX, secret::generate_token(32));
+
+    let new_api_key = NewApiKey {
+        user_id,
+        name: name.to_owned(),
+        prefix: key[..API_KEY_DISPLAY_PREFIX_LEN].to_owned(),
+        key_hash: secret::hash_token(&key),
+        scopes: scopes.join(","),
+        expires_at: expires_in_days.map(|days| (chrono::Utc::now() + chrono::Duration::days(days)).naive_utc()),
+    };
+
+    let api_key = state.create_api_key(&new_api_key).await?;
+    tracing::info!("api key {} created for user {}", api_key.id, user_id);
+
+    Ok(CreatedApiKey { api_key, key })
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+/// Resolves an API key into access claims carrying the current roles of its owner and the key scopes.
+///
+/// A key counts as a token of its owner issued when the key was created, revocation watermarks such as a logout of
+/// every session or a password change disable the keys created before them.
+pub async fn authenticate(key: &str, state: &SharedState) -> Result<AccessClaim, AuthError> {
+    let (api_key, claims) = resolve(key, state).await?;
+
+    let touch_before = chrono::Utc::now() - chrono::Duration::seconds(API_KEY_TOUCH_INTERVAL_SECONDS);
+    if api_key.last_used_at.is_none_or(|last_used_at| last_used_at.and_utc() < touch_before) {
+        state.touch_api_key(api_key.id).await?;
+    }
+    Ok(claims)
+}
+
+/// Same as [`authenticate`] without recording the use of the key, for token introspection.
+pub async fn inspect(key: &str, state: &SharedState) -> Result<AccessClaim, AuthError> {
+    let (_, claims) = resolve(key, state).await?;
+    Ok(claims)
+}
+
+async fn resolve(key: &str, state: &SharedState) -> Result<(ApiKey, AccessClaim), AuthError> {
+    let api_key = state.get_active_api_key_by_hash(&secret::hash_token(key))
+        .await?
+        .ok_or_else(|| {
+            tracing::error!("unknown, expired or revoked api key");
+            AuthError::InvalidBearerToken
+        })?;
+
+    let user = state.get_user_by_id(api_key.user_id).await?;
+    if !user.active {
+        tracing::error!("api key {} of inactive user {}", api_key.id, user.id);
+        return Err(AuthError::InvalidBearerToken)
+    }
+
+    let now = chrono::Utc::now();
+    let exp = api_key.expires_at
+        .map(|expires_at| expires_at.and_utc())
+        .unwrap_or_else(|| now + chrono::Duration::seconds(state.config.jwt_exp_access_token_second));
+    let created_at = api_key.created_at.map_or(now, |created_at| created_at.and_utc());
+
+    let claims = AccessClaim {
+        sub: user.id.to_string(),
+        iss: state.config.jwt_issuer.to_owned(),
+        aud: state.config.default_audience(),
+        jti: api_key.id.to_string(),
+        iat: created_at.timestamp() as usize,
+        iat_ms: Some(created_at.timestamp_millis() as u64),
+        exp: exp.timestamp() as usize,
+        typ: JwtTokenType::AccessToken as u8,
+        roles: Roles::parse(&user.roles),
+        fid: api_key.id.to_string(),
+        scopes: Some(api_key.scopes.split(',').filter(|s| !s.is_empty()).map(str::to_owned).collect()),
+        cid: None,
+        act: None,
+    };
+
+    if state.config.jwt_enable_revoked_tokens && token_service::is_revoked(&claims, state).await? {
+        tracing::error!("api key {} revoked by a watermark", api_key.id);
+        return Err(AuthError::InvalidBearerToken)
+    }
+    Ok((api_key, claims))
+}
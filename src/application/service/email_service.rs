@@ -0,0 +1,104 @@
+use crate::application::{
+    config::Config,
+    repository::user_repository::UserRepositoryExt,
+    security::{
+        auth::{self, AuthError},
+        jwt::{ClaimsMethods, EmailVerificationClaim, JwtTokenType, decode_token},
+    },
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+use crate::infra::mail::MailMessage;
+
+pub async fn send_verification_email(user: &User, state: &SharedState) -> Result<(), AuthError> {
+    let message = verification_message(user, &state.config)?;
+    state.mailer.send(&message).await?;
+    tracing::info!("verification email sent to user {}", user.id);
+    Ok(())
+}
+
+fn verification_message(user: &User, config: &Config) -> Result<MailMessage, AuthError> {
+    let token = auth::create_email_verification_token(user, config)?;
+    let link = format!("{}/verify-email?token={}", config.app_base_url, token);
+
+    Ok(MailMessage {
+        from: config.mail_from.to_owned(),
+        to: user.email.to_owned(),
+        subject: "Verify your email address".to_owned(),
+        body: format!(
+            "Hi {},\n\nPlease confirm your email address by opening the link below:\n\n{}\n\nThe link expires in {} hours.\n",
+            user.name,
+            link,
+            config.email_verification_exp_seconds / 3600,
+        ),
+    })
+}
+
+pub async fn verify_email(token: &str, state: &SharedState) -> Result<User, AuthError> {
+    let claims = decode_token::<EmailVerificationClaim>(token, &state.config)?;
+    if claims.get_typ() != JwtTokenType::EmailVerificationToken {
+        return Err(AuthError::InvalidToken)
+    }
+
+    let user_id = claims.sub.parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AuthError::InvalidToken,
+            _ => AuthError::from(e),
+        })?;
+
+    // The link was sent to an address the user no longer uses.
+    if user.email != claims.email {
+        return Err(AuthError::InvalidToken)
+    }
+
+    let user = state.mark_email_verified(user_id).await?;
+    tracing::info!("email of user {} verified", user.id);
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+    use crate::application::config;
+    use crate::infra::mail::{MailTransport, OutboxTransport};
+
+    #[tokio::test]
+    async fn verification_link_read_from_the_outbox_verifies_the_address() {
+        let config = config::test_config();
+        let user = User {
+            id: Uuid::new_v4(),
+            name: "Alice".to_owned(),
+            username: "alice".to_owned(),
+            email: "alice@example.com".to_owned(),
+            password_hash: String::new(),
+            active: true,
+            roles: "user".to_owned(),
+            email_verified_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            created_at: None,
+            updated_at: None,
+        };
+
+        let dir = std::env::temp_dir().join(format!("outbox-{}", Uuid::new_v4()));
+        let outbox = OutboxTransport::open(dir.to_str().unwrap());
+        outbox.send(&verification_message(&user, &config).unwrap()).await.unwrap();
+
+        let messages = outbox.messages().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].to, user.email);
+
+        let token = messages[0].body
+            .split_once("/verify-email?token=")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .unwrap();
+        let claims = decode_token::<EmailVerificationClaim>(token, &config).unwrap();
+        assert_eq!(claims.get_typ(), JwtTokenType::EmailVerificationToken);
+        assert_eq!(claims.sub, user.id.to_string());
+        assert_eq!(claims.email, user.email);
+    }
+}
@@ -0,0 +1,105 @@
+use std::net::IpAddr;
+use std::time::Duration;
+use redis::{AsyncCommands, RedisResult};
+use crate::application::{
+    constant::{LOGIN_FAILURES_IDENTIFIER_KEY, LOGIN_FAILURES_IP_KEY, LOGIN_LOCKOUT_IDENTIFIER_KEY, LOGIN_LOCKOUT_IP_KEY},
+    security::auth::AuthError,
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+
+/// Rejects the attempt while the identifier or the client IP is locked out.
+pub async fn check_lockout(identifier: &str, ip: IpAddr, state: &SharedState) -> Result<(), AuthError> {
+    let mut redis = state.cache.lock().await;
+
+    for key in [identifier_key(LOGIN_LOCKOUT_IDENTIFIER_KEY, identifier), ip_key(LOGIN_LOCKOUT_IP_KEY, ip)] {
+        let ttl: i64 = redis.ttl(&key).await?;
+        if ttl > 0 {
+            tracing::error!("login locked out: {}", key);
+            return Err(AuthError::AccountLocked(ttl as u64))
+        }
+    }
+    Ok(())
+}
+
+/// Rejects a login of `user` while its account or the client IP is locked out.
+pub async fn check_user_lockout(user: &User, ip: IpAddr, state: &SharedState) -> Result<(), AuthError> {
+    check_lockout(&user.id.to_string(), ip, state).await
+}
+
+/// Identifier the failures of a login are counted under: the user ID when `identifier` names an account, so its
+/// username and email share one counter, `identifier` itself otherwise.
+pub fn attempt_identifier(identifier: &str, user: Option<&User>) -> String {
+    match user {
+        Some(user) => user.id.to_string(),
+        None => identifier.to_owned(),
+    }
+}
+
+/// Delay applied before checking the credentials, doubling with every failure past the threshold.
+pub async fn failure_delay(identifier: &str, state: &SharedState) -> RedisResult<Duration> {
+    let mut redis = state.cache.lock().await;
+    let failures: Option<u64> = redis.get(identifier_key(LOGIN_FAILURES_IDENTIFIER_KEY, identifier)).await?;
+
+    let config = &state.config;
+    let excess = failures.unwrap_or(0).saturating_sub(config.login_delay_after_failures);
+    if excess == 0 {
+        return Ok(Duration::ZERO)
+    }
+    let delay = 1u64.checked_shl(excess as u32 - 1).unwrap_or(u64::MAX).min(config.login_max_delay_seconds);
+    Ok(Duration::from_secs(delay))
+}
+
+/// Counts a failed attempt, locking the identifier or the IP out once its limit is reached.
+pub async fn record_failure(identifier: &str, ip: IpAddr, state: &SharedState) -> RedisResult<()> {
+    let config = &state.config;
+    let counters = [
+        (identifier_key(LOGIN_FAILURES_IDENTIFIER_KEY, identifier), identifier_key(LOGIN_LOCKOUT_IDENTIFIER_KEY, identifier), config.login_max_failures_per_identifier),
+        (ip_key(LOGIN_FAILURES_IP_KEY, ip), ip_key(LOGIN_LOCKOUT_IP_KEY, ip), config.login_max_failures_per_ip),
+    ];
+
+    let mut redis = state.cache.lock().await;
+    for (failures_key, lockout_key, max_failures) in counters {
+        let failures: u64 = redis.incr(&failures_key, 1).await?;
+        if failures == 1 {
+            let _: () = redis.expire(&failures_key, config.login_failure_window_seconds as i64).await?;
+        }
+
+        if failures >= max_failures {
+            tracing::error!("too many failed logins, locking out: {}", lockout_key);
+            let _: () = redis.set_ex(&lockout_key, failures, config.login_lockout_seconds).await?;
+            let _: () = redis.del(&failures_key).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Forgets the failures of an identifier after a successful login, the IP counter keeps running.
+pub async fn record_success(identifier: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.del(identifier_key(LOGIN_FAILURES_IDENTIFIER_KEY, identifier)).await
+}
+
+pub async fn unlock_identifier(identifier: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.del(&[
+        identifier_key(LOGIN_FAILURES_IDENTIFIER_KEY, identifier),
+        identifier_key(LOGIN_LOCKOUT_IDENTIFIER_KEY, identifier),
+    ]).await
+}
+
+pub async fn unlock_ip(ip: IpAddr, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.del(&[
+        ip_key(LOGIN_FAILURES_IP_KEY, ip),
+        ip_key(LOGIN_LOCKOUT_IP_KEY, ip),
+    ]).await
+}
+
+fn identifier_key(prefix: &str, identifier: &str) -> String {
+    format!("{}.{}", prefix, identifier.trim().to_lowercase())
+}
+
+fn ip_key(prefix: &str, ip: IpAddr) -> String {
+    format!("{}.{}", prefix, ip)
+}
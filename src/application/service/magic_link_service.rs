@@ -0,0 +1,84 @@
+use crate::application::{
+    constant::MAGIC_LINK_USED_KEY,
+    repository::user_repository::UserRepositoryExt,
+    security::{
+        auth::{self, AuthError},
+        jwt::{ClaimsMethods, JwtTokenType, MagicLinkClaim, decode_token},
+    },
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+use crate::infra::mail::MailMessage;
+
+/// Emails a login link to the owner of `identifier`, if there is one allowed to log in.
+///
+/// Callers must answer the same way whether or not the account exists.
+pub async fn request_magic_link(identifier: &str, state: &SharedState) -> Result<(), AuthError> {
+    let Some(user) = state.get_user_by_identifier(identifier).await? else {
+        tracing::info!("magic link requested for unknown identifier");
+        return Ok(())
+    };
+    if !user.active || user.email_verified_at.is_none() {
+        tracing::info!("magic link requested for inactive or unverified user {}", user.id);
+        return Ok(())
+    }
+
+    let token = auth::create_magic_link_token(&user, &state.config)?;
+    let link = format!("{}/magic-link?token={}", state.config.app_base_url, token);
+
+    let message = MailMessage {
+        from: state.config.mail_from.to_owned(),
+        to: user.email.to_owned(),
+        subject: "Your login link".to_owned(),
+        body: format!(
+            "Hi {},\n\nOpen the link below to log in:\n\n{}\n\nThe link expires in {} minutes and can be used once. If you did not ask for it, ignore this email.\n",
+            user.name,
+            link,
+            state.config.magic_link_exp_seconds / 60,
+        ),
+    };
+
+    state.mailer.send(&message).await?;
+    tracing::info!("magic link sent to user {}", user.id);
+    Ok(())
+}
+
+/// Consumes a login link, returning the user it logs in.
+pub async fn consume_magic_link(token: &str, state: &SharedState) -> Result<User, AuthError> {
+    let claims = decode_token::<MagicLinkClaim>(token, &state.config)?;
+    if claims.get_typ() != JwtTokenType::MagicLinkToken {
+        return Err(AuthError::InvalidToken)
+    }
+
+    let user_id = claims.sub.parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AuthError::InvalidToken,
+            _ => AuthError::from(e),
+        })?;
+
+    // The link was sent to an address the user no longer uses.
+    if user.email != claims.email {
+        return Err(AuthError::InvalidToken)
+    }
+    if !user.active || user.email_verified_at.is_none() {
+        return Err(AuthError::WrongCredentials)
+    }
+
+    // The link is remembered until it expires, so it logs in once only.
+    let used_key = format!("{}.{}", MAGIC_LINK_USED_KEY, claims.jti);
+    let ttl = claims.get_exp().saturating_sub(claims.get_iat()).max(1) as u64;
+    let mut redis = state.cache.lock().await;
+    let consumed: bool = redis::cmd("SET").arg(&used_key).arg(1).arg("NX").arg("EX").arg(ttl)
+        .query_async::<Option<String>>(&mut *redis)
+        .await?
+        .is_some();
+    drop(redis);
+    if !consumed {
+        return Err(AuthError::InvalidToken)
+    }
+
+    tracing::info!("user {} logged in with a magic link", user.id);
+    Ok(user)
+}
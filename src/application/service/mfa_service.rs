@@ -0,0 +1,226 @@
+use std::net::IpAddr;
+use data_encoding::BASE32_NOPAD;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use redis::AsyncCommands;
+use serde::Serialize;
+use crate::application::{
+    constant::{MFA_PENDING_USED_KEY, MFA_TOTP_USED_KEY},
+    repository::{mfa_repository::MfaRepositoryExt, user_repository::UserRepositoryExt},
+    security::{
+        auth::AuthError,
+        jwt::{ClaimsMethods, JwtTokenType, MfaPendingClaim, decode_token},
+        totp,
+    },
+    service::login_attempt_service,
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Number of time steps accepted on each side of the current one, to absorb clock drift.
+const TOTP_SKEW_STEPS: u64 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// Generates a new pending TOTP secret, it is only enforced once confirmed with a code.
+pub async fn start_totp_enrollment(user: &User, state: &SharedState) -> Result<TotpEnrollment, AuthError> {
+    if user.totp_enabled_at.is_some() {
+        return Err(AuthError::MfaAlreadyEnabled)
+    }
+
+    let secret = totp::generate_secret();
+    state.set_pending_totp_secret(user.id, &secret).await?;
+
+    Ok(TotpEnrollment {
+        otpauth_uri: totp::provisioning_uri(&state.config.totp_issuer, &user.email, &secret),
+        secret,
+    })
+}
+
+/// Enables two-factor authentication once the user proves their authenticator app works.
+pub async fn confirm_totp_enrollment(user: &User, code: &str, state: &SharedState) -> Result<RecoveryCodes, AuthError> {
+    if user.totp_enabled_at.is_some() {
+        return Err(AuthError::MfaAlreadyEnabled)
+    }
+    let secret = user.totp_secret.as_deref().ok_or(AuthError::MfaNotEnrolled)?;
+    verify_totp(user, secret, code, state).await?;
+
+    let (codes, code_hashes) = generate_recovery_codes(state).await?;
+    state.enable_totp(user.id, &code_hashes).await?;
+    tracing::info!("two-factor authentication enabled for user {}", user.id);
+
+    Ok(RecoveryCodes { recovery_codes: codes })
+}
+
+pub async fn disable_totp(user: &User, code: &str, ip: IpAddr, state: &SharedState) -> Result<(), AuthError> {
+    verify_second_factor_counted(user, code, ip, state).await?;
+    state.disable_totp(user.id).await?;
+    tracing::info!("two-factor authentication disabled for user {}", user.id);
+    Ok(())
+}
+
+/// Issues a new set of recovery codes, the previous ones stop working.
+pub async fn regenerate_recovery_codes(
+    user: &User,
+    code: &str,
+    ip: IpAddr,
+    state: &SharedState,
+) -> Result<RecoveryCodes, AuthError> {
+    verify_second_factor_counted(user, code, ip, state).await?;
+
+    let (codes, code_hashes) = generate_recovery_codes(state).await?;
+    state.replace_recovery_codes(user.id, &code_hashes).await?;
+    tracing::info!("recovery codes regenerated for user {}", user.id);
+
+    Ok(RecoveryCodes { recovery_codes: codes })
+}
+
+/// Completes a login started with a password, `mfa_token` can only be exchanged once.
+///
+/// Returns the user along with the audience requested at login. Failures are counted per user and client IP by the
+/// login attempt service.
+pub async fn verify_mfa(mfa_token: &str, code: &str, ip: IpAddr, state: &SharedState) -> Result<(User, Vec<String>), AuthError> {
+    let claims = decode_token::<MfaPendingClaim>(mfa_token, &state.config)?;
+    if claims.get_typ() != JwtTokenType::MfaPendingToken {
+        return Err(AuthError::InvalidToken)
+    }
+
+    login_attempt_service::check_lockout(&claims.sub, ip, state).await?;
+
+    let used_key = format!("{}.{}", MFA_PENDING_USED_KEY, claims.jti);
+    {
+        let mut redis = state.cache.lock().await;
+        let used: bool = redis.exists(&used_key).await?;
+        if used {
+            return Err(AuthError::InvalidToken)
+        }
+    }
+
+    let user_id = claims.sub.parse().map_err(|_| AuthError::InvalidToken)?;
+    let user = state.get_user_by_id(user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AuthError::InvalidToken,
+            _ => AuthError::from(e),
+        })?;
+    if !user.active || user.totp_enabled_at.is_none() {
+        return Err(AuthError::InvalidToken)
+    }
+
+    match verify_second_factor(&user, code, state).await {
+        Ok(()) => {}
+        Err(AuthError::InvalidMfaCode) => {
+            login_attempt_service::record_failure(&claims.sub, ip, state).await?;
+            return Err(AuthError::InvalidMfaCode)
+        }
+        Err(e) => return Err(e),
+    }
+
+    let ttl = claims.get_exp().saturating_sub(claims.get_iat()).max(1) as u64;
+    let mut redis = state.cache.lock().await;
+    let consumed: bool = redis::cmd("SET").arg(&used_key).arg(1).arg("NX").arg("EX").arg(ttl)
+        .query_async::<Option<String>>(&mut *redis)
+        .await?
+        .is_some();
+    if !consumed {
+        return Err(AuthError::InvalidToken)
+    }
+    drop(redis);
+
+    login_attempt_service::record_success(&claims.sub, state).await?;
+    Ok((user, claims.aud))
+}
+
+/// Same as [`verify_second_factor`], with failures counted and locked out like the MFA login challenge.
+async fn verify_second_factor_counted(user: &User, code: &str, ip: IpAddr, state: &SharedState) -> Result<(), AuthError> {
+    let identifier = user.id.to_string();
+    login_attempt_service::check_lockout(&identifier, ip, state).await?;
+
+    match verify_second_factor(user, code, state).await {
+        Ok(()) => {}
+        Err(AuthError::InvalidMfaCode) => {
+            login_attempt_service::record_failure(&identifier, ip, state).await?;
+            return Err(AuthError::InvalidMfaCode)
+        }
+        Err(e) => return Err(e),
+    }
+
+    login_attempt_service::record_success(&identifier, state).await?;
+    Ok(())
+}
+
+/// Accepts either a TOTP code or an unused recovery code.
+async fn verify_second_factor(user: &User, code: &str, state: &SharedState) -> Result<(), AuthError> {
+    let secret = match (&user.totp_secret, user.totp_enabled_at) {
+        (Some(secret), Some(_)) => secret,
+        _ => return Err(AuthError::MfaNotEnrolled),
+    };
+
+    if code.trim().bytes().all(|b| b.is_ascii_digit()) {
+        return verify_totp(user, secret, code, state).await
+    }
+
+    let code = normalize_recovery_code(code);
+    for recovery_code in state.list_unused_recovery_codes(user.id).await? {
+        if state.hasher.compare(&code, &recovery_code.code_hash).await? {
+            if !state.use_recovery_code(recovery_code.id).await? {
+                break
+            }
+            tracing::info!("recovery code used by user {}", user.id);
+            return Ok(())
+        }
+    }
+    Err(AuthError::InvalidMfaCode)
+}
+
+/// Verifies a TOTP code, each time step can only be used once per user.
+async fn verify_totp(user: &User, secret: &str, code: &str, state: &SharedState) -> Result<(), AuthError> {
+    let now = chrono::Utc::now().timestamp() as u64;
+    let step = totp::verify(secret, code, now, TOTP_SKEW_STEPS).ok_or(AuthError::InvalidMfaCode)?;
+
+    let key = format!("{}.{}.{}", MFA_TOTP_USED_KEY, user.id, step);
+    let ttl = (2 * TOTP_SKEW_STEPS + 1) * totp::TOTP_STEP_SECONDS;
+
+    let mut redis = state.cache.lock().await;
+    let fresh = redis::cmd("SET").arg(&key).arg(1).arg("NX").arg("EX").arg(ttl)
+        .query_async::<Option<String>>(&mut *redis)
+        .await?
+        .is_some();
+    if !fresh {
+        tracing::error!("replayed totp code for user {}", user.id);
+        return Err(AuthError::InvalidMfaCode)
+    }
+    Ok(())
+}
+
+/// Generates the recovery codes shown once to the user along with their hashes to store.
+async fn generate_recovery_codes(state: &SharedState) -> Result<(Vec<String>, Vec<String>), AuthError> {
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    let mut code_hashes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let mut buffer = [0u8; 10];
+        OsRng.fill_bytes(&mut buffer);
+        let code = BASE32_NOPAD.encode(&buffer)[..10].to_lowercase();
+
+        code_hashes.push(state.hasher.hash(code.as_str(), &state.config.password_hash_policy).await?);
+        codes.push(format!("{}-{}", &code[..5], &code[5..]));
+    }
+    Ok((codes, code_hashes))
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
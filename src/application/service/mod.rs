@@ -1 +1,10 @@
-pub mod token_service;
\ No newline at end of file
+pub mod token_service;
+pub mod email_service;
+pub mod password_service;
+pub mod magic_link_service;
+pub mod login_attempt_service;
+pub mod mfa_service;
+pub mod api_key_service;
+pub mod session_service;
+pub mod oauth_service;
+pub mod webauthn_service;
\ No newline at end of file
@@ -0,0 +1,611 @@
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::application::{
+    constant::{
+        OAUTH_CODE_REDIS_KEY, OAUTH_DEVICE_CODE_REDIS_KEY, OAUTH_DEVICE_POLL_REDIS_KEY, OAUTH_DEVICE_USER_CODE_REDIS_KEY,
+    },
+    repository::{oauth_client_repository::OAuthClientRepositoryExt, user_repository::UserRepositoryExt},
+    security::{
+        auth::{self, AuthError, ClientGrant},
+        jwt::{AccessClaim, Actor, ClaimsMethods, JwtTokenType, RefreshClaim, decode_token},
+        oauth::{self, OAuthError},
+        role::Roles,
+        secret,
+    },
+    service::{api_key_service, token_service},
+    state::SharedState,
+};
+use crate::domain::entities::oauth_client::{
+    GRANT_AUTHORIZATION_CODE, GRANT_CLIENT_CREDENTIALS, GRANT_DEVICE_CODE, GRANT_REFRESH_TOKEN, NewOAuthClient, OAuthClient,
+};
+
+/// Parameters of an authorization request, see RFC 6749 section 4.1.1 and RFC 7636 section 4.3.
+#[derive(Debug)]
+pub struct AuthorizationRequest<'a> {
+    pub response_type: &'a str,
+    pub client_id: &'a str,
+    pub redirect_uri: &'a str,
+    pub scope: Option<&'a str>,
+    pub code_challenge: Option<&'a str>,
+    pub code_challenge_method: Option<&'a str>,
+}
+
+/// Authorization code waiting to be exchanged, stored under the hash of the code.
+#[derive(Debug, Serialize, Deserialize)]
+struct AuthorizationCode {
+    client_id: String,
+    user_id: Uuid,
+    redirect_uri: String,
+    scopes: Vec<String>,
+    code_challenge: String,
+}
+
+/// Device authorization waiting for the user, stored under the hash of the device code.
+#[derive(Debug, Serialize, Deserialize)]
+struct DeviceAuthorization {
+    client_id: String,
+    user_code: String,
+    scopes: Vec<String>,
+    /// Set once the user decided, `Some(None)` when they denied the request.
+    decision: Option<Option<Uuid>>,
+}
+
+/// Successful response of the device authorization endpoint, see RFC 8628 section 3.2.
+#[derive(Debug, Serialize)]
+pub struct DeviceCodeResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: u64,
+    pub interval: u64,
+}
+
+/// Successful response of the token endpoint, see RFC 6749 section 5.1.
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    pub scope: String,
+}
+
+/// Answer of the introspection endpoint, see RFC 7662 section 2.2.
+///
+/// Only `active` is set for tokens that are invalid, expired or revoked.
+#[derive(Debug, Default, Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Roles>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Admin impersonating the subject, see RFC 8693 section 4.1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+/// A newly registered client, the only time its secret is returned.
+#[derive(Debug, Serialize)]
+pub struct RegisteredClient {
+    #[serde(flatten)]
+    pub client: OAuthClient,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+
+pub async fn register_client(
+    name: &str,
+    redirect_uris: &[String],
+    grant_types: &[String],
+    scopes: &[String],
+    roles: &str,
+    confidential: bool,
+    state: &SharedState,
+) -> Result<RegisteredClient, AuthError> {
+    let client_secret = confidential.then(|| secret::generate_token(32));
+
+    let new_client = NewOAuthClient {
+        client_id: secret::generate_token(16),
+        client_secret_hash: client_secret.as_deref().map(secret::hash_token),
+        name: name.to_owned(),
+        redirect_uris: redirect_uris.join(","),
+        grant_types: grant_types.join(","),
+        scopes: scopes.join(","),
+        roles: roles.to_owned(),
+    };
+
+    let client = state.create_oauth_client(&new_client).await?;
+    tracing::info!("oauth client {} registered", client.client_id);
+
+    Ok(RegisteredClient { client, client_secret })
+}
+
+/// Looks up the client of an authorization request.
+///
+/// Requests with an unknown client or redirect URI must not be redirected back, see RFC 6749 section 4.1.2.1.
+pub async fn find_client_for_redirect(
+    client_id: &str,
+    redirect_uri: &str,
+    state: &SharedState,
+) -> Result<OAuthClient, OAuthError> {
+    let client = state.get_oauth_client(client_id)
+        .await?
+        .ok_or(OAuthError::InvalidClient)?;
+    if !client.allows_redirect_uri(redirect_uri) {
+        return Err(OAuthError::InvalidRequest("redirect_uri is not registered for this client".to_owned()))
+    }
+    Ok(client)
+}
+
+/// Validates an authorization request of `client`, returning the scopes to grant.
+pub fn validate_authorization_request(
+    client: &OAuthClient,
+    request: &AuthorizationRequest<'_>,
+) -> Result<Vec<String>, OAuthError> {
+    if request.response_type != "code" {
+        return Err(OAuthError::UnsupportedResponseType)
+    }
+    if !client.allows_grant(GRANT_AUTHORIZATION_CODE) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+    if request.code_challenge.is_none_or(str::is_empty) || request.code_challenge_method != Some("S256") {
+        return Err(OAuthError::InvalidRequest("code_challenge with code_challenge_method S256 is required".to_owned()))
+    }
+
+    resolve_scopes(client, oauth::parse_scope(request.scope))
+}
+
+/// Issues an authorization code on behalf of `user_id` and returns the URI to redirect the user agent to.
+pub async fn authorize(
+    request: &AuthorizationRequest<'_>,
+    user_id: Uuid,
+    authorization_state: Option<&str>,
+    state: &SharedState,
+) -> Result<String, OAuthError> {
+    let client = find_client_for_redirect(request.client_id, request.redirect_uri, state).await?;
+    let scopes = validate_authorization_request(&client, request)?;
+
+    let code = secret::generate_token(32);
+    let authorization_code = AuthorizationCode {
+        client_id: client.client_id,
+        user_id,
+        redirect_uri: request.redirect_uri.to_owned(),
+        scopes,
+        code_challenge: request.code_challenge.unwrap_or_default().to_owned(),
+    };
+    let value = serde_json::to_string(&authorization_code).map_err(|_| OAuthError::ServerError)?;
+
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.set_ex(authorization_code_key(&code), value, state.config.oauth_code_exp_seconds).await?;
+    drop(redis);
+    tracing::info!("authorization code issued to client {} for user {}", authorization_code.client_id, user_id);
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(authorization_state) = authorization_state {
+        params.push(("state", authorization_state));
+    }
+    Ok(redirect_uri_with(request.redirect_uri, &params))
+}
+
+/// Appends query parameters to a redirect URI.
+pub fn redirect_uri_with(redirect_uri: &str, params: &[(&str, &str)]) -> String {
+    let query = params.iter()
+        .map(|(name, value)| format!("{}={}", name, utf8_percent_encode(value, NON_ALPHANUMERIC)))
+        .collect::<Vec<_>>()
+        .join("&");
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", redirect_uri, separator, query)
+}
+
+/// Authenticates a client of the token endpoint, confidential clients must present their secret.
+pub async fn authenticate_client(
+    client_id: &str,
+    client_secret: Option<&str>,
+    state: &SharedState,
+) -> Result<OAuthClient, OAuthError> {
+    let client = state.get_oauth_client(client_id)
+        .await?
+        .ok_or(OAuthError::InvalidClient)?;
+
+    let authenticated = match (&client.client_secret_hash, client_secret) {
+        (Some(secret_hash), Some(client_secret)) => {
+            secret::constant_time_eq(secret_hash.as_bytes(), secret::hash_token(client_secret).as_bytes())
+        }
+        (None, None) => true,
+        _ => false,
+    };
+    if !authenticated {
+        tracing::error!("oauth client authentication failed: {}", client_id);
+        return Err(OAuthError::InvalidClient)
+    }
+    Ok(client)
+}
+
+pub async fn exchange_authorization_code(
+    client: &OAuthClient,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+    state: &SharedState,
+) -> Result<TokenResponse, OAuthError> {
+    if !client.allows_grant(GRANT_AUTHORIZATION_CODE) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+
+    let mut redis = state.cache.lock().await;
+    let value: Option<String> = redis.get_del(authorization_code_key(code)).await?;
+    drop(redis);
+
+    let authorization_code: AuthorizationCode = value
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .ok_or_else(|| OAuthError::InvalidGrant("invalid or expired authorization code".to_owned()))?;
+
+    if authorization_code.client_id != client.client_id || authorization_code.redirect_uri != redirect_uri {
+        return Err(OAuthError::InvalidGrant("authorization code was issued to another client or redirect_uri".to_owned()))
+    }
+    if !oauth::verify_pkce(code_verifier, &authorization_code.code_challenge) {
+        return Err(OAuthError::InvalidGrant("code_verifier does not match the code_challenge".to_owned()))
+    }
+
+    issue_user_token(client, authorization_code.user_id, authorization_code.scopes, state).await
+}
+
+/// Starts a device authorization for `client`, the user then enters the user code on another device.
+pub async fn create_device_code(
+    client: &OAuthClient,
+    scope: Option<&str>,
+    state: &SharedState,
+) -> Result<DeviceCodeResponse, OAuthError> {
+    if !client.allows_grant(GRANT_DEVICE_CODE) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+    let scopes = resolve_scopes(client, oauth::parse_scope(scope))?;
+
+    let device_code = secret::generate_token(32);
+    let user_code = secret::generate_user_code();
+    let device_authorization = DeviceAuthorization {
+        client_id: client.client_id.to_owned(),
+        user_code: user_code.to_owned(),
+        scopes,
+        decision: None,
+    };
+    let value = serde_json::to_string(&device_authorization).map_err(|_| OAuthError::ServerError)?;
+    let device_code_hash = secret::hash_token(&device_code);
+    let ttl = state.config.oauth_device_code_exp_seconds;
+
+    let options = SetOptions::default()
+        .conditional_set(ExistenceCheck::NX)
+        .with_expiration(SetExpiry::EX(ttl));
+
+    let mut redis = state.cache.lock().await;
+    let created: Option<String> = redis.set_options(device_user_code_key(&user_code), &device_code_hash, options).await?;
+    if created.is_none() {
+        // Two pending authorizations drew the same user code, let the device retry.
+        return Err(OAuthError::ServerError)
+    }
+    let _: () = redis.set_ex(device_code_key(&device_code_hash), value, ttl).await?;
+    drop(redis);
+    tracing::info!("device code issued to client {}", client.client_id);
+
+    let verification_uri = format!("{}/oauth/device", state.config.app_base_url);
+    let verification_uri_complete = redirect_uri_with(&verification_uri, &[("user_code", &user_code)]);
+    Ok(DeviceCodeResponse {
+        device_code,
+        user_code,
+        verification_uri,
+        verification_uri_complete,
+        expires_in: ttl,
+        interval: state.config.oauth_device_poll_interval_seconds,
+    })
+}
+
+/// Records the decision of the signed-in user about the device authorization identified by `user_code`.
+pub async fn decide_device_authorization(
+    user_code: &str,
+    user_id: Uuid,
+    approved: bool,
+    state: &SharedState,
+) -> Result<(), OAuthError> {
+    let user_code = secret::normalize_user_code(user_code);
+
+    let mut redis = state.cache.lock().await;
+    let device_code_hash: Option<String> = redis.get_del(device_user_code_key(&user_code)).await?;
+    let device_code_hash = device_code_hash.ok_or_else(|| OAuthError::InvalidGrant("invalid or expired user code".to_owned()))?;
+
+    let value: Option<String> = redis.get(device_code_key(&device_code_hash)).await?;
+    let mut device_authorization: DeviceAuthorization = value
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .ok_or_else(|| OAuthError::InvalidGrant("invalid or expired user code".to_owned()))?;
+
+    device_authorization.decision = Some(approved.then_some(user_id));
+    let value = serde_json::to_string(&device_authorization).map_err(|_| OAuthError::ServerError)?;
+
+    // Keep the expiration of the device code, and do not bring it back if it just expired.
+    let options = SetOptions::default()
+        .conditional_set(ExistenceCheck::XX)
+        .with_expiration(SetExpiry::KEEPTTL);
+    let updated: Option<String> = redis.set_options(device_code_key(&device_code_hash), value, options).await?;
+    drop(redis);
+    if updated.is_none() {
+        return Err(OAuthError::InvalidGrant("invalid or expired user code".to_owned()))
+    }
+
+    tracing::info!(
+        "device authorization of client {} {} by user {}",
+        device_authorization.client_id,
+        if approved { "approved" } else { "denied" },
+        user_id,
+    );
+    Ok(())
+}
+
+/// Answers a device polling the token endpoint, issuing tokens once the user approved it.
+pub async fn exchange_device_code(
+    client: &OAuthClient,
+    device_code: &str,
+    state: &SharedState,
+) -> Result<TokenResponse, OAuthError> {
+    if !client.allows_grant(GRANT_DEVICE_CODE) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+
+    let device_code_hash = secret::hash_token(device_code);
+    let key = device_code_key(&device_code_hash);
+
+    let mut redis = state.cache.lock().await;
+    let value: Option<String> = redis.get(&key).await?;
+    let device_authorization: DeviceAuthorization = value
+        .and_then(|value| serde_json::from_str(&value).ok())
+        .ok_or(OAuthError::ExpiredToken)?;
+
+    if device_authorization.client_id != client.client_id {
+        return Err(OAuthError::InvalidGrant("device code was issued to another client".to_owned()))
+    }
+
+    let user_id = match device_authorization.decision {
+        None => {
+            let interval = state.config.oauth_device_poll_interval_seconds;
+            let options = SetOptions::default()
+                .conditional_set(ExistenceCheck::NX)
+                .with_expiration(SetExpiry::EX(interval));
+            let polled: Option<String> = redis.set_options(device_poll_key(&device_code_hash), 1, options).await?;
+            return Err(match polled {
+                Some(_) => OAuthError::AuthorizationPending,
+                None => OAuthError::SlowDown,
+            })
+        }
+        Some(None) => {
+            let _: () = redis.del(&key).await?;
+            return Err(OAuthError::AccessDenied)
+        }
+        Some(Some(user_id)) => user_id,
+    };
+
+    // Only the first poll after the approval gets the tokens.
+    let deleted: usize = redis.del(&key).await?;
+    drop(redis);
+    if deleted == 0 {
+        return Err(OAuthError::ExpiredToken)
+    }
+
+    issue_user_token(client, user_id, device_authorization.scopes, state).await
+}
+
+/// Issues a token pair delegated to `client` on behalf of `user_id`.
+///
+/// A refresh token is only returned when the client may use the refresh token grant.
+async fn issue_user_token(
+    client: &OAuthClient,
+    user_id: Uuid,
+    scopes: Vec<String>,
+    state: &SharedState,
+) -> Result<TokenResponse, OAuthError> {
+    let user = state.get_user_by_id(user_id).await?;
+    if !user.active {
+        return Err(OAuthError::InvalidGrant("user is inactive".to_owned()))
+    }
+
+    let grant = ClientGrant {
+        client_id: client.client_id.to_owned(),
+        scopes,
+    };
+    let family_id = Uuid::new_v4().to_string();
+    let token = auth::create_client_token_in_family(&user, &family_id, Some(&grant), &state.config.default_audience(), &state.config)?;
+
+    let refresh_token = if client.allows_grant(GRANT_REFRESH_TOKEN) {
+        token_service::store_refresh_family(&token.refresh_claim, state).await?;
+        Some(token.refresh_token)
+    } else {
+        None
+    };
+    tracing::info!("tokens issued to client {} for user {}", client.client_id, user.id);
+
+    Ok(TokenResponse {
+        access_token: token.access_token,
+        token_type: "Bearer",
+        expires_in: state.config.jwt_exp_access_token_second,
+        refresh_token,
+        scope: grant.scopes.join(" "),
+    })
+}
+
+pub async fn refresh(
+    client: &OAuthClient,
+    refresh_token: &str,
+    state: &SharedState,
+) -> Result<TokenResponse, OAuthError> {
+    if !client.allows_grant(GRANT_REFRESH_TOKEN) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+
+    let claims = auth::decode_refresh_token(refresh_token, state).await?;
+    if claims.cid.as_deref() != Some(client.client_id.as_str()) {
+        return Err(OAuthError::InvalidGrant("refresh token was issued to another client".to_owned()))
+    }
+
+    let token = auth::rotate_refresh_token(&claims, state).await?;
+
+    Ok(TokenResponse {
+        access_token: token.access_token,
+        token_type: "Bearer",
+        expires_in: state.config.jwt_exp_access_token_second,
+        refresh_token: Some(token.refresh_token),
+        scope: claims.scopes.unwrap_or_default().join(" "),
+    })
+}
+
+/// Issues an access token to the client itself, carrying the roles configured for the client.
+pub async fn client_credentials(
+    client: &OAuthClient,
+    scope: Option<&str>,
+    state: &SharedState,
+) -> Result<TokenResponse, OAuthError> {
+    if !client.is_confidential() || !client.allows_grant(GRANT_CLIENT_CREDENTIALS) {
+        return Err(OAuthError::UnauthorizedClient)
+    }
+
+    let scopes = resolve_scopes(client, oauth::parse_scope(scope))?;
+    let now = chrono::Utc::now();
+
+    let claims = AccessClaim {
+        sub: client.client_id.to_owned(),
+        iss: state.config.jwt_issuer.to_owned(),
+        aud: state.config.default_audience(),
+        jti: Uuid::new_v4().to_string(),
+        iat: now.timestamp() as usize,
+        iat_ms: Some(now.timestamp_millis() as u64),
+        exp: (now + chrono::Duration::seconds(state.config.jwt_exp_access_token_second)).timestamp() as usize,
+        typ: JwtTokenType::AccessToken as u8,
+        roles: Roles::parse(&client.roles),
+        fid: String::new(),
+        scopes: Some(scopes.clone()),
+        cid: Some(client.client_id.to_owned()),
+        act: None,
+    };
+    let access_token = auth::encode_token(&claims, &state.config)?;
+    tracing::info!("client credentials token issued to client {}", client.client_id);
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: state.config.jwt_exp_access_token_second,
+        refresh_token: None,
+        scope: scopes.join(" "),
+    })
+}
+
+/// Tells whether `token` is an active access token, refresh token or API key, and what it carries.
+pub async fn introspect(token: &str, state: &SharedState) -> Result<IntrospectionResponse, OAuthError> {
+    if api_key_service::is_api_key(token) {
+        return match api_key_service::inspect(token, state).await {
+            Ok(claims) => Ok(access_introspection(claims)),
+            Err(AuthError::InvalidBearerToken) => Ok(IntrospectionResponse::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Refresh tokens carry no roles, they fail to decode as access claims.
+    let response = if let Ok(claims) = decode_token::<AccessClaim>(token, &state.config) {
+        if claims.get_typ() != JwtTokenType::AccessToken || is_revoked(&claims, state).await? {
+            return Ok(IntrospectionResponse::default())
+        }
+        access_introspection(claims)
+    } else if let Ok(claims) = decode_token::<RefreshClaim>(token, &state.config) {
+        if claims.get_typ() != JwtTokenType::RefreshToken || is_revoked(&claims, state).await? {
+            return Ok(IntrospectionResponse::default())
+        }
+        IntrospectionResponse {
+            active: true,
+            token_type: Some("refresh_token"),
+            iss: Some(claims.iss),
+            sub: Some(claims.sub),
+            aud: Some(claims.aud),
+            jti: Some(claims.jti),
+            iat: Some(claims.iat),
+            exp: Some(claims.exp),
+            roles: None,
+            scope: claims.scopes.map(|scopes| scopes.join(" ")),
+            client_id: claims.cid,
+            act: None,
+        }
+    } else {
+        IntrospectionResponse::default()
+    };
+
+    Ok(response)
+}
+
+fn access_introspection(claims: AccessClaim) -> IntrospectionResponse {
+    IntrospectionResponse {
+        active: true,
+        token_type: Some("access_token"),
+        iss: Some(claims.iss),
+        sub: Some(claims.sub),
+        aud: Some(claims.aud),
+        jti: Some(claims.jti),
+        iat: Some(claims.iat),
+        exp: Some(claims.exp),
+        roles: Some(claims.roles),
+        scope: claims.scopes.map(|scopes| scopes.join(" ")),
+        client_id: claims.cid,
+        act: claims.act,
+    }
+}
+
+async fn is_revoked<T: std::fmt::Debug + ClaimsMethods + Send + Sync>(
+    claims: &T,
+    state: &SharedState,
+) -> Result<bool, OAuthError> {
+    if !state.config.jwt_enable_revoked_tokens {
+        return Ok(false)
+    }
+    Ok(token_service::is_revoked(claims, state).await?)
+}
+
+/// Scopes to grant, all the client scopes when none are requested.
+fn resolve_scopes(client: &OAuthClient, requested: Vec<String>) -> Result<Vec<String>, OAuthError> {
+    if requested.is_empty() {
+        return Ok(client.scopes())
+    }
+    if let Some(scope) = requested.iter().find(|scope| !client.allows_scope(scope)) {
+        return Err(OAuthError::InvalidScope(format!("scope not allowed for this client: {}", scope)))
+    }
+    Ok(requested)
+}
+
+fn authorization_code_key(code: &str) -> String {
+    format!("{}.{}", OAUTH_CODE_REDIS_KEY, secret::hash_token(code))
+}
+
+fn device_code_key(device_code_hash: &str) -> String {
+    format!("{}.{}", OAUTH_DEVICE_CODE_REDIS_KEY, device_code_hash)
+}
+
+fn device_user_code_key(user_code: &str) -> String {
+    format!("{}.{}", OAUTH_DEVICE_USER_CODE_REDIS_KEY, user_code)
+}
+
+fn device_poll_key(device_code_hash: &str) -> String {
+    format!("{}.{}", OAUTH_DEVICE_POLL_REDIS_KEY, device_code_hash)
+}
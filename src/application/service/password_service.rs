@@ -0,0 +1,99 @@
+use redis::AsyncCommands;
+use uuid::Uuid;
+use crate::application::{
+    constant::{PASSWORD_RESET_REDIS_KEY, PASSWORD_RESET_REDIS_USER_KEY},
+    repository::user_repository::UserRepositoryExt,
+    security::{auth::AuthError, secret},
+    service::token_service,
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+use crate::infra::mail::MailMessage;
+
+/// Emails a one-time reset link to the owner of `identifier`, if there is one.
+///
+/// Callers must answer the same way whether or not the account exists.
+pub async fn request_password_reset(identifier: &str, state: &SharedState) -> Result<(), AuthError> {
+    let Some(user) = state.get_user_by_identifier(identifier).await? else {
+        tracing::info!("password reset requested for unknown identifier");
+        return Ok(())
+    };
+    if !user.active {
+        tracing::info!("password reset requested for inactive user {}", user.id);
+        return Ok(())
+    }
+
+    let token = store_reset_token(&user, state).await?;
+    let link = format!("{}/reset-password?token={}", state.config.app_base_url, token);
+
+    let message = MailMessage {
+        from: state.config.mail_from.to_owned(),
+        to: user.email.to_owned(),
+        subject: "Reset your password".to_owned(),
+        body: format!(
+            "Hi {},\n\nSomeone asked to reset your password. If it was you, open the link below:\n\n{}\n\nThe link expires in {} minutes and can be used once. If it was not you, ignore this email.\n",
+            user.name,
+            link,
+            state.config.password_reset_exp_seconds / 60,
+        ),
+    };
+
+    state.mailer.send(&message).await?;
+    tracing::info!("password reset email sent to user {}", user.id);
+    Ok(())
+}
+
+/// Consumes a reset token, stores the new password and revokes every existing session of the user.
+pub async fn reset_password(token: &str, new_password: &str, state: &SharedState) -> Result<User, AuthError> {
+    let user_id = consume_reset_token(token, state)
+        .await?
+        .ok_or(AuthError::InvalidToken)?;
+
+    let password_hash = state.hasher.hash(new_password, &state.config.password_hash_policy).await?;
+    let user = state.update_password_hash(user_id, &password_hash)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AuthError::InvalidToken,
+            _ => AuthError::from(e),
+        })?;
+
+    token_service::revoke_user_tokens(&user.id.to_string(), state).await?;
+    tracing::info!("password of user {} reset", user.id);
+    Ok(user)
+}
+
+async fn store_reset_token(user: &User, state: &SharedState) -> Result<String, AuthError> {
+    let token = secret::generate_token(32);
+    let token_hash = secret::hash_token(&token);
+    let ttl = state.config.password_reset_exp_seconds as u64;
+    let user_key = format!("{}.{}", PASSWORD_RESET_REDIS_USER_KEY, user.id);
+
+    let mut redis = state.cache.lock().await;
+
+    // Only the latest link stays valid.
+    let previous_hash: Option<String> = redis.get(&user_key).await?;
+    if let Some(previous_hash) = previous_hash {
+        let _: () = redis.del(reset_token_key(&previous_hash)).await?;
+    }
+
+    let _: () = redis.set_ex(reset_token_key(&token_hash), user.id.to_string(), ttl).await?;
+    let _: () = redis.set_ex(&user_key, &token_hash, ttl).await?;
+    Ok(token)
+}
+
+async fn consume_reset_token(token: &str, state: &SharedState) -> Result<Option<Uuid>, AuthError> {
+    let token_hash = secret::hash_token(token);
+
+    let mut redis = state.cache.lock().await;
+    let user_id: Option<String> = redis.get_del(reset_token_key(&token_hash)).await?;
+    let Some(user_id) = user_id else {
+        return Ok(None)
+    };
+    let _: () = redis.del(format!("{}.{}", PASSWORD_RESET_REDIS_USER_KEY, user_id)).await?;
+
+    Ok(user_id.parse().ok())
+}
+
+fn reset_token_key(token_hash: &str) -> String {
+    format!("{}.{}", PASSWORD_RESET_REDIS_KEY, token_hash)
+}
@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use redis::{AsyncCommands, RedisResult, Script};
+use crate::application::{
+    constant::{SESSION_REDIS_KEY, SESSION_REDIS_USER_KEY},
+    security::{auth::{AuthError, JwtToken}, jwt::ClaimsMethods, role::Roles},
+    service::token_service,
+    state::SharedState,
+};
+use crate::domain::entities::{session::Session, user::User};
+
+/// What happens to a login that would exceed the session limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionLimitStrategy {
+    /// Sign out the oldest sessions to make room for the new one.
+    #[default]
+    EvictOldest,
+    /// Refuse the login until a session is ended.
+    Reject,
+}
+
+impl FromStr for SessionLimitStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "evict_oldest" => Ok(Self::EvictOldest),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown session limit strategy: {}", s)),
+        }
+    }
+}
+
+/// Caps the number of simultaneous sessions of a user, `0` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct SessionLimits {
+    pub max_per_user: usize,
+    /// Overrides of `max_per_user` keyed by role, the most permissive one applies.
+    pub max_per_role: HashMap<String, usize>,
+    pub strategy: SessionLimitStrategy,
+}
+
+impl SessionLimits {
+    /// Parses role overrides written as `admin=3,user=5`.
+    pub fn parse_role_limits(value: &str) -> Result<HashMap<String, usize>, String> {
+        value.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (role, max) = entry.split_once('=').ok_or_else(|| format!("invalid role limit: {}", entry))?;
+                let max = max.trim().parse().map_err(|_| format!("invalid role limit: {}", entry))?;
+                Ok((role.trim().to_owned(), max))
+            })
+            .collect()
+    }
+
+    pub fn limit_for(&self, roles: &Roles) -> Option<usize> {
+        let role_limits: Vec<usize> = roles.iter()
+            .filter_map(|role| self.max_per_role.get(&role.to_string()).copied())
+            .collect();
+
+        let limit = if role_limits.is_empty() {
+            self.max_per_user
+        } else if role_limits.contains(&0) {
+            0
+        } else {
+            role_limits.into_iter().max().unwrap_or_default()
+        };
+
+        (limit > 0).then_some(limit)
+    }
+}
+
+/// Client a session was opened from.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    pub user_agent: Option<String>,
+}
+
+/// Records the session started by a freshly issued token pair, within the session limits of `user`.
+///
+/// Counting and recording happen in one script, so concurrent logins cannot exceed the limit or evict each
+/// other's new session. The sessions evicted to make room are revoked once the script returned.
+pub async fn create_session(user: &User, token: &JwtToken, client: &ClientInfo, state: &SharedState) -> Result<Session, AuthError> {
+    let limits = &state.config.session_limits;
+    let limit = limits.limit_for(&Roles::parse(&user.roles));
+
+    let now = chrono::Utc::now();
+    let session = Session {
+        id: token.refresh_claim.fid.to_owned(),
+        user_id: token.refresh_claim.sub.to_owned(),
+        user_agent: client.user_agent.to_owned(),
+        ip: Some(client.ip.to_string()),
+        refresh_exp: token.refresh_claim.exp,
+        created_at: now.timestamp(),
+        last_seen_at: now.timestamp(),
+    };
+
+    if limit.is_some() {
+        prune_sessions(&session.user_id, state).await?;
+    }
+
+    let script = Script::new(r#"
+        local limit = tonumber(ARGV[1])
+        local evicted = {}
+        if limit > 0 then
+            local count = redis.call('ZCARD', KEYS[1])
+            if count >= limit then
+                if ARGV[2] == '1' then
+                    return false
+                end
+                local oldest = redis.call('ZPOPMIN', KEYS[1], count - limit + 1)
+                for i = 1, #oldest, 2 do
+                    table.insert(evicted, oldest[i])
+                end
+            end
+        end
+
+        redis.call('HSET', KEYS[2], unpack(ARGV, 6))
+        redis.call('EXPIREAT', KEYS[2], ARGV[5])
+        redis.call('ZADD', KEYS[1], ARGV[4], ARGV[3])
+        return evicted
+    "#);
+
+    let mut invocation = script.key(user_sessions_key(&session.user_id));
+    invocation.key(session_key(&session.id))
+        .arg(limit.unwrap_or(0))
+        .arg(u8::from(limits.strategy == SessionLimitStrategy::Reject))
+        .arg(&session.id)
+        // Sessions are ordered by the issue time of their first tokens, as compared against revocation watermarks.
+        .arg(token.refresh_claim.get_iat_ms())
+        .arg(session.refresh_exp);
+    for (field, value) in session_fields(&session) {
+        invocation.arg(field).arg(value);
+    }
+
+    let evicted: Option<Vec<String>> = {
+        let mut redis = state.cache.lock().await;
+        invocation.invoke_async(&mut *redis).await?
+    };
+    let Some(evicted) = evicted else {
+        let limit = limit.unwrap_or_default();
+        tracing::error!("session limit of {} reached for user {}", limit, user.id);
+        return Err(AuthError::SessionLimitReached(limit))
+    };
+
+    for evicted_id in &evicted {
+        revoke_session(&session.user_id, evicted_id, state).await?;
+        tracing::info!("session {} of user {} evicted by session limit", evicted_id, user.id);
+    }
+    Ok(session)
+}
+
+/// Forgets the sessions of a user that expired with their refresh token, so they do not count against its limit.
+async fn prune_sessions(user_id: &str, state: &SharedState) -> RedisResult<()> {
+    let key = user_sessions_key(user_id);
+    let mut redis = state.cache.lock().await;
+
+    let session_ids: Vec<String> = redis.zrange(&key, 0, -1).await?;
+    for session_id in &session_ids {
+        let exists: bool = redis.exists(session_key(session_id)).await?;
+        if !exists {
+            let _: () = redis.zrem(&key, session_id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Extends the session to the refresh token issued by a refresh.
+pub async fn rotate_session(token: &JwtToken, state: &SharedState) -> RedisResult<()> {
+    let key = session_key(&token.refresh_claim.fid);
+    let mut redis = state.cache.lock().await;
+
+    let exists: bool = redis.exists(&key).await?;
+    if !exists {
+        return Ok(())
+    }
+
+    let _: () = redis.hset_multiple(&key, &[
+        ("refresh_exp", token.refresh_claim.exp.to_string()),
+        ("last_seen_at", chrono::Utc::now().timestamp().to_string()),
+    ]).await?;
+    redis.expire_at(&key, token.refresh_claim.exp as i64).await
+}
+
+/// Updates the last seen time of a session, without resurrecting one that ended.
+pub async fn touch_session(session_id: &str, state: &SharedState) -> RedisResult<()> {
+    let script = Script::new(r#"
+        if redis.call('EXISTS', KEYS[1]) == 1 then
+            redis.call('HSET', KEYS[1], 'last_seen_at', ARGV[1])
+        end
+        return 0
+    "#);
+
+    let mut redis = state.cache.lock().await;
+    script.key(session_key(session_id))
+        .arg(chrono::Utc::now().timestamp())
+        .invoke_async(&mut *redis)
+        .await
+}
+
+pub async fn get_session(session_id: &str, state: &SharedState) -> RedisResult<Option<Session>> {
+    let mut redis = state.cache.lock().await;
+    let fields: HashMap<String, String> = redis.hgetall(session_key(session_id)).await?;
+    Ok(session_from_fields(&fields))
+}
+
+/// Lists the sessions of a user, oldest first.
+pub async fn list_sessions(user_id: &str, state: &SharedState) -> RedisResult<Vec<Session>> {
+    let mut redis = state.cache.lock().await;
+    let session_ids: Vec<String> = redis.zrange(user_sessions_key(user_id), 0, -1).await?;
+
+    let mut sessions = Vec::with_capacity(session_ids.len());
+    for session_id in session_ids {
+        let fields: HashMap<String, String> = redis.hgetall(session_key(&session_id)).await?;
+        match session_from_fields(&fields) {
+            Some(session) => sessions.push(session),
+            // The session expired with its refresh token.
+            None => redis.zrem(user_sessions_key(user_id), &session_id).await?,
+        }
+    }
+    Ok(sessions)
+}
+
+/// Ends a session, every token of its refresh token family is revoked.
+pub async fn revoke_session(user_id: &str, session_id: &str, state: &SharedState) -> RedisResult<()> {
+    token_service::revoke_refresh_family(session_id, state).await?;
+    remove_session(user_id, session_id, state).await
+}
+
+/// Forgets the session record only, used when its tokens are already revoked some other way.
+pub async fn remove_session(user_id: &str, session_id: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.del(session_key(session_id)).await?;
+    redis.zrem(user_sessions_key(user_id), session_id).await
+}
+
+/// Ends the sessions of a user created before the millisecond `before_ms`, whose tokens were revoked by a watermark.
+pub async fn remove_sessions_before(user_id: &str, before_ms: u64, state: &SharedState) -> RedisResult<()> {
+    let session_ids: Vec<String> = {
+        let mut redis = state.cache.lock().await;
+        redis.zrangebyscore(user_sessions_key(user_id), "-inf", format!("({}", before_ms)).await?
+    };
+
+    for session_id in &session_ids {
+        token_service::revoke_refresh_family(session_id, state).await?;
+        remove_session(user_id, session_id, state).await?;
+    }
+    Ok(())
+}
+
+fn session_fields(session: &Session) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("id", session.id.to_owned()),
+        ("user_id", session.user_id.to_owned()),
+        ("refresh_exp", session.refresh_exp.to_string()),
+        ("created_at", session.created_at.to_string()),
+        ("last_seen_at", session.last_seen_at.to_string()),
+    ];
+    if let Some(user_agent) = &session.user_agent {
+        fields.push(("user_agent", user_agent.to_owned()));
+    }
+    if let Some(ip) = &session.ip {
+        fields.push(("ip", ip.to_owned()));
+    }
+    fields
+}
+
+fn session_from_fields(fields: &HashMap<String, String>) -> Option<Session> {
+    Some(Session {
+        id: fields.get("id")?.to_owned(),
+        user_id: fields.get("user_id")?.to_owned(),
+        user_agent: fields.get("user_agent").cloned(),
+        ip: fields.get("ip").cloned(),
+        refresh_exp: fields.get("refresh_exp")?.parse().ok()?,
+        created_at: fields.get("created_at")?.parse().ok()?,
+        last_seen_at: fields.get("last_seen_at")?.parse().ok()?,
+    })
+}
+
+fn session_key(session_id: &str) -> String {
+    format!("{}.{}", SESSION_REDIS_KEY, session_id)
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("{}.{}", SESSION_REDIS_USER_KEY, user_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use super::*;
+    use crate::application::{repository::user_repository::UserRepositoryExt, security::auth, state::test_state};
+    use crate::domain::entities::user::NewUser;
+
+    fn limits(max_per_user: usize, max_per_role: &str) -> SessionLimits {
+        SessionLimits {
+            max_per_user,
+            max_per_role: SessionLimits::parse_role_limits(max_per_role).unwrap(),
+            strategy: SessionLimitStrategy::default(),
+        }
+    }
+
+    #[test]
+    fn parses_role_limits() {
+        let parsed = SessionLimits::parse_role_limits(" admin = 3, user=5 ,").unwrap();
+        assert_eq!(parsed, HashMap::from([("admin".to_owned(), 3), ("user".to_owned(), 5)]));
+        assert!(SessionLimits::parse_role_limits("").unwrap().is_empty());
+        assert!(SessionLimits::parse_role_limits("admin").is_err());
+        assert!(SessionLimits::parse_role_limits("admin=three").is_err());
+        assert!(SessionLimits::parse_role_limits("admin=-1").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_the_user_limit() {
+        assert_eq!(limits(2, "admin=3").limit_for(&Roles::parse("user")), Some(2));
+        assert_eq!(limits(2, "").limit_for(&Roles::default()), Some(2));
+        assert_eq!(limits(0, "admin=3").limit_for(&Roles::parse("user")), None);
+    }
+
+    #[test]
+    fn most_permissive_role_limit_wins() {
+        let limits = limits(2, "admin=0,user=5,support=1");
+
+        assert_eq!(limits.limit_for(&Roles::parse("support")), Some(1));
+        assert_eq!(limits.limit_for(&Roles::parse("user,support")), Some(5));
+        assert_eq!(limits.limit_for(&Roles::parse("admin,user")), None);
+    }
+
+    #[test]
+    fn parses_strategies() {
+        assert_eq!("evict_oldest".parse(), Ok(SessionLimitStrategy::EvictOldest));
+        assert_eq!("reject".parse(), Ok(SessionLimitStrategy::Reject));
+        assert!("Reject".parse::<SessionLimitStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn evicted_session_tokens_are_revoked() {
+        let mut state = test_state().await;
+        std::sync::Arc::get_mut(&mut state).unwrap().config.session_limits.max_per_user = 1;
+        let name = format!("session-{}", uuid::Uuid::new_v4().simple());
+        let new_user = NewUser {
+            name: name.to_owned(),
+            username: name.to_owned(),
+            email: format!("{}@example.com", name),
+            password_hash: String::new(),
+            roles: "user".to_owned(),
+        };
+        let user = state.create_user(&new_user).await.unwrap();
+        let client = ClientInfo { ip: Ipv4Addr::LOCALHOST.into(), user_agent: None };
+        let aud = auth::token_audience(None, &state.config).unwrap();
+
+        let first = auth::issue_token(&user, &aud, &client, &state).await.unwrap();
+        let second = auth::issue_token(&user, &aud, &client, &state).await.unwrap();
+
+        let sessions = list_sessions(&user.id.to_string(), &state).await.unwrap();
+        assert_eq!(sessions.iter().map(|session| &session.id).collect::<Vec<_>>(), vec![&second.refresh_claim.fid]);
+        assert!(token_service::is_revoked(&first.access_claim, &state).await.unwrap());
+        assert!(!token_service::is_revoked(&second.access_claim, &state).await.unwrap());
+    }
+}
@@ -1,10 +1,22 @@
+use std::collections::HashMap;
 use redis::aio::MultiplexedConnection;
-use redis::{AsyncCommands, RedisResult};
+use redis::{AsyncCommands, ErrorKind, RedisError, RedisResult, Script};
+use serde::Serialize;
 use tokio::sync::MutexGuard;
-use crate::application::constant::{JWT_REDIS_REVOKED_TOKENS_KEY, JWT_REDIS_REVOKE_GLOBAL_BEFORE, JWT_REDIS_REVOKE_USER_BEFORE_KEY};
-use crate::application::security::jwt::ClaimsMethods;
+use crate::application::config::Config;
+use crate::application::constant::{
+    JWT_REDIS_REFRESH_FAMILY_KEY, JWT_REDIS_REVOKED_FAMILY_KEY, JWT_REDIS_REVOKED_TOKENS_EXP_KEY, JWT_REDIS_REVOKED_TOKENS_KEY, JWT_REDIS_REVOKE_GLOBAL_BEFORE,
+    JWT_REDIS_REVOKE_USER_BEFORE_KEY,
+};
+use crate::application::security::jwt::{ClaimsMethods, RefreshClaim};
+use crate::application::service::session_service;
 use crate::application::state::SharedState;
 
+/// Most expired revocations swept by each new one.
+const REVOKED_TOKENS_SWEEP_LIMIT: usize = 100;
+/// Revoked tokens read per round trip by [`list_revocations`].
+const REVOKED_TOKENS_PAGE_SIZE: usize = 500;
+
 pub async fn is_revoked<T: std::fmt::Debug + ClaimsMethods + Send + Sync> (
     claims: &T,
     state: &SharedState,
@@ -35,6 +47,12 @@ pub async fn is_revoked<T: std::fmt::Debug + ClaimsMethods + Send + Sync> (
         return Ok(true)
     }
 
+    let family_revoked = is_family_revoked(claims, &mut redis).await?;
+    if family_revoked {
+        tracing::error!("access denied (token family revoked): {:#?}", claims);
+        return Ok(true)
+    }
+
     drop(redis);
     Ok(false)
 }
@@ -46,32 +64,325 @@ async fn is_token_revoked<T: ClaimsMethods + Send + Sync>(
     redis.hexists(JWT_REDIS_REVOKED_TOKENS_KEY, claims.get_jti()).await
 }
 
+async fn is_family_revoked<T: ClaimsMethods + Send + Sync>(
+    claims: &T,
+    redis: &mut MutexGuard<'_, MultiplexedConnection>
+) -> RedisResult<bool> {
+    match claims.get_fid() {
+        Some(family_id) => redis.exists(revoked_family_key(family_id)).await,
+        None => Ok(false),
+    }
+}
+
 async fn is_user_revoked<T: ClaimsMethods + Send + Sync>(
     claims: &T,
     redis: &mut MutexGuard<'_, MultiplexedConnection>
 ) -> RedisResult<bool> {
     let user_id = claims.get_sub();
-    let opt_ext: Option<String> = redis.hget(JWT_REDIS_REVOKE_USER_BEFORE_KEY, user_id).await?;
-    if let Some(exp) = opt_ext {
-        let global_exp = exp.parse::<usize>().unwrap();
-        if global_exp >= claims.get_iat() {
-            return Ok(true)
-        }
-    }
-    Ok(false)
+    let watermark: Option<String> = redis.hget(JWT_REDIS_REVOKE_USER_BEFORE_KEY, user_id).await?;
+    is_issued_before(claims, watermark.as_deref())
 }
 
 async fn is_global_revoked<T: ClaimsMethods + Sync + Send>(
     claims: &T,
     redis: &mut MutexGuard<'_, MultiplexedConnection>,
 ) -> RedisResult<bool> {
-    let opt_exp: Option<String> = redis.get(JWT_REDIS_REVOKE_GLOBAL_BEFORE).await?;
-    if let Some(exp) = opt_exp {
-        let global_exp = exp.parse::<usize>().unwrap();
-        if global_exp >= claims.get_iat() {
-            return Ok(true)
+    let watermark: Option<String> = redis.get(JWT_REDIS_REVOKE_GLOBAL_BEFORE).await?;
+    is_issued_before(claims, watermark.as_deref())
+}
+
+fn is_issued_before<T: ClaimsMethods>(claims: &T, watermark: Option<&str>) -> RedisResult<bool> {
+    let Some(watermark) = watermark else {
+        return Ok(false)
+    };
+    Ok(claims.get_iat_ms() < parse_watermark(watermark)?)
+}
+
+/// Writes a watermark revoking the tokens issued before `before_ms`, as seconds with a millisecond fraction.
+fn format_watermark(before_ms: u64) -> String {
+    format!("{}.{:03}", before_ms / 1000, before_ms % 1000)
+}
+
+/// Reads a watermark as the first millisecond it lets through.
+///
+/// Whole seconds, as written by the admin API and earlier versions, revoke every token issued up to the end of
+/// that second.
+fn parse_watermark(watermark: &str) -> RedisResult<u64> {
+    let before_ms = match watermark.split_once('.') {
+        None => watermark.parse::<u64>().ok().and_then(|seconds| seconds.checked_add(1)?.checked_mul(1000)),
+        Some((seconds, millis)) if millis.len() == 3 && millis.bytes().all(|b| b.is_ascii_digit()) => {
+            seconds.parse::<u64>().ok().and_then(|seconds| seconds.checked_mul(1000)?.checked_add(millis.parse().ok()?))
         }
+        Some(_) => None,
+    };
+    before_ms.ok_or_else(|| RedisError::from((ErrorKind::TypeError, "malformed revocation watermark", watermark.to_owned())))
+}
+
+/// Last second a watermark revokes tokens of, as reported by [`list_revocations`].
+fn watermark_seconds(watermark: &str) -> RedisResult<usize> {
+    Ok((parse_watermark(watermark)?.saturating_sub(1) / 1000) as usize)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RefreshFamilyStatus {
+    /// The presented refresh token is the latest one of its family.
+    Current,
+    /// The presented refresh token was already rotated, the family is compromised.
+    Reused,
+    /// The family does not exist anymore (expired or revoked).
+    Unknown,
+}
+
+pub async fn store_refresh_family(
+    claims: &RefreshClaim,
+    state: &SharedState,
+) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    let ttl = state.config.jwt_exp_refresh_token_second as u64;
+    redis.set_ex(refresh_family_key(&claims.fid), &claims.jti, ttl).await
+}
+
+/// Moves the family of `old_claims` on to `new_claims`, only if `old_claims` is still its latest token.
+///
+/// The check and the update are a single script, so two refreshes racing with the same token cannot both
+/// succeed. The rotated token is not revoked by its ID: the family record alone rejects it, as a reuse.
+pub async fn rotate_refresh_family(
+    old_claims: &RefreshClaim,
+    new_claims: &RefreshClaim,
+    state: &SharedState,
+) -> RedisResult<RefreshFamilyStatus> {
+    let script = Script::new(r#"
+        local current = redis.call('GET', KEYS[1])
+        if not current then
+            return 0
+        end
+        if current ~= ARGV[1] then
+            return 2
+        end
+        redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+        return 1
+    "#);
+
+    let mut redis = state.cache.lock().await;
+    let status: u8 = script.key(refresh_family_key(&old_claims.fid))
+        .arg(&old_claims.jti)
+        .arg(&new_claims.jti)
+        .arg(state.config.jwt_exp_refresh_token_second)
+        .invoke_async(&mut *redis)
+        .await?;
+
+    Ok(match status {
+        1 => RefreshFamilyStatus::Current,
+        2 => RefreshFamilyStatus::Reused,
+        _ => RefreshFamilyStatus::Unknown,
+    })
+}
+
+/// Revokes every token of a family, access tokens issued before the last rotation included, and discards its
+/// refresh record.
+///
+/// The revocation lasts as long as a refresh token, which outlives every token of the family issued so far.
+pub async fn revoke_refresh_family(family_id: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.del(refresh_family_key(family_id)).await?;
+    redis.set_ex(revoked_family_key(family_id), 1, state.config.jwt_exp_refresh_token_second as u64).await
+}
+
+pub async fn revoke_token<T: ClaimsMethods + Send + Sync>(
+    claims: &T,
+    state: &SharedState,
+) -> RedisResult<()> {
+    revoke_token_id(claims.get_jti(), claims.get_exp(), state).await
+}
+
+/// Revokes a single token until `exp`.
+///
+/// Revocations are indexed by expiration time and each new one sweeps a batch of those that outlived their token,
+/// so the hash only grows with the tokens still alive.
+pub async fn revoke_token_id(jti: &str, exp: usize, state: &SharedState) -> RedisResult<()> {
+    let script = Script::new(r#"
+        redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+        redis.call('ZADD', KEYS[2], ARGV[2], ARGV[1])
+        local expired = redis.call('ZRANGEBYSCORE', KEYS[2], '-inf', '(' .. ARGV[3], 'LIMIT', 0, ARGV[4])
+        if #expired > 0 then
+            redis.call('HDEL', KEYS[1], unpack(expired))
+            redis.call('ZREM', KEYS[2], unpack(expired))
+        end
+        return 0
+    "#);
+
+    let mut redis = state.cache.lock().await;
+    script.key(JWT_REDIS_REVOKED_TOKENS_KEY)
+        .key(JWT_REDIS_REVOKED_TOKENS_EXP_KEY)
+        .arg(jti)
+        .arg(exp)
+        .arg(expired_before(&state.config))
+        .arg(REVOKED_TOKENS_SWEEP_LIMIT)
+        .invoke_async(&mut *redis)
+        .await
+}
+
+pub async fn unrevoke_token_id(jti: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.hdel(JWT_REDIS_REVOKED_TOKENS_KEY, jti).await?;
+    redis.zrem(JWT_REDIS_REVOKED_TOKENS_EXP_KEY, jti).await
+}
+
+/// Expiration time before which a token is rejected whatever its revocations, given the validation leeway.
+fn expired_before(config: &Config) -> i64 {
+    chrono::Utc::now().timestamp() - config.jwt_validation_leeway_seconds
+}
+
+fn refresh_family_key(family_id: &str) -> String {
+    format!("{}.{}", JWT_REDIS_REFRESH_FAMILY_KEY, family_id)
+}
+
+fn revoked_family_key(family_id: &str) -> String {
+    format!("{}.{}", JWT_REDIS_REVOKED_FAMILY_KEY, family_id)
+}
+
+/// Revokes every token of a user issued before now, tokens issued from now on stay valid.
+pub async fn revoke_user_tokens(user_id: &str, state: &SharedState) -> RedisResult<()> {
+    let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+    set_user_watermark(user_id, format_watermark(now_ms), state).await
+}
+
+/// Revokes every token of a user issued at or before the second `before`.
+pub async fn revoke_user_tokens_before(user_id: &str, before: usize, state: &SharedState) -> RedisResult<()> {
+    set_user_watermark(user_id, before.to_string(), state).await
+}
+
+async fn set_user_watermark(user_id: &str, watermark: String, state: &SharedState) -> RedisResult<()> {
+    let before_ms = parse_watermark(&watermark)?;
+    {
+        let mut redis = state.cache.lock().await;
+        let _: () = redis.hset(JWT_REDIS_REVOKE_USER_BEFORE_KEY, user_id, watermark).await?;
     }
+    session_service::remove_sessions_before(user_id, before_ms, state).await
+}
 
-    Ok(false)
-}
\ No newline at end of file
+pub async fn unrevoke_user_tokens(user_id: &str, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.hdel(JWT_REDIS_REVOKE_USER_BEFORE_KEY, user_id).await
+}
+
+pub async fn revoke_global_tokens_before(before: usize, state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.set(JWT_REDIS_REVOKE_GLOBAL_BEFORE, before).await
+}
+
+pub async fn unrevoke_global_tokens(state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.del(JWT_REDIS_REVOKE_GLOBAL_BEFORE).await
+}
+
+#[derive(Debug, Serialize)]
+pub struct Revocations {
+    /// Tokens issued at or before this timestamp are revoked for everyone.
+    pub global_before: Option<usize>,
+    /// Per-user "revoke before" watermarks, keyed by user ID.
+    pub users: HashMap<String, usize>,
+    /// Individually revoked tokens, keyed by JWT ID with their expiration time.
+    pub tokens: HashMap<String, usize>,
+}
+
+pub async fn list_revocations(state: &SharedState) -> RedisResult<Revocations> {
+    let (global_before, users): (Option<String>, HashMap<String, String>) = {
+        let mut redis = state.cache.lock().await;
+        (
+            redis.get(JWT_REDIS_REVOKE_GLOBAL_BEFORE).await?,
+            redis.hgetall(JWT_REDIS_REVOKE_USER_BEFORE_KEY).await?,
+        )
+    };
+
+    // The connection is released between pages, so requests checking revocations are not held up by a long list.
+    let expired_before = expired_before(&state.config);
+    let mut tokens = HashMap::new();
+    let mut cursor = 0u64;
+    loop {
+        let (next_cursor, page): (u64, HashMap<String, usize>) = {
+            let mut redis = state.cache.lock().await;
+            redis::cmd("HSCAN")
+                .arg(JWT_REDIS_REVOKED_TOKENS_KEY)
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(REVOKED_TOKENS_PAGE_SIZE)
+                .query_async(&mut *redis)
+                .await?
+        };
+        tokens.extend(page.into_iter().filter(|(_, exp)| *exp as i64 >= expired_before));
+        if next_cursor == 0 {
+            break
+        }
+        cursor = next_cursor;
+    }
+
+    Ok(Revocations {
+        global_before: global_before.as_deref().map(watermark_seconds).transpose()?,
+        users: users.into_iter()
+            .map(|(user_id, watermark)| Ok((user_id, watermark_seconds(&watermark)?)))
+            .collect::<RedisResult<_>>()?,
+        tokens,
+    })
+}
+
+pub async fn clear_revocations(state: &SharedState) -> RedisResult<()> {
+    let mut redis = state.cache.lock().await;
+    redis.del(&[
+        JWT_REDIS_REVOKE_GLOBAL_BEFORE,
+        JWT_REDIS_REVOKE_USER_BEFORE_KEY,
+        JWT_REDIS_REVOKED_TOKENS_KEY,
+        JWT_REDIS_REVOKED_TOKENS_EXP_KEY,
+    ]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_second_watermarks_revoke_the_whole_second() {
+        assert_eq!(parse_watermark("1700000000").unwrap(), 1_700_000_001_000);
+        assert_eq!(watermark_seconds("1700000000").unwrap(), 1_700_000_000);
+    }
+
+    #[test]
+    fn millisecond_watermarks_round_trip() {
+        for before_ms in [1_700_000_000_000, 1_700_000_000_007, 1_700_000_000_999] {
+            assert_eq!(parse_watermark(&format_watermark(before_ms)).unwrap(), before_ms);
+        }
+        assert_eq!(format_watermark(1_700_000_000_007), "1700000000.007");
+        assert_eq!(watermark_seconds("1700000000.500").unwrap(), 1_700_000_000);
+        assert_eq!(watermark_seconds("1700000000.000").unwrap(), 1_699_999_999);
+    }
+
+    #[test]
+    fn tokens_issued_before_the_watermark_are_revoked() {
+        let claims = |iat_ms: u64| RefreshClaim {
+            sub: "1".to_owned(),
+            iss: String::new(),
+            aud: Vec::new(),
+            jti: String::new(),
+            iat: (iat_ms / 1000) as usize,
+            iat_ms: Some(iat_ms),
+            exp: 0,
+            typ: 0,
+            fid: String::new(),
+            scopes: None,
+            cid: None,
+        };
+
+        assert!(is_issued_before(&claims(1_700_000_000_499), Some("1700000000.500")).unwrap());
+        assert!(!is_issued_before(&claims(1_700_000_000_500), Some("1700000000.500")).unwrap());
+        assert!(is_issued_before(&claims(1_700_000_000_999), Some("1700000000")).unwrap());
+        assert!(!is_issued_before(&claims(1_700_000_001_000), Some("1700000000")).unwrap());
+        assert!(!is_issued_before(&claims(0), None).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_watermarks() {
+        for watermark in ["", "abc", "1700000000.5", "1700000000.-50", "1700000000.5000", "1.2.3", "-1"] {
+            assert!(parse_watermark(watermark).is_err(), "watermark {:?}", watermark);
+        }
+    }
+}
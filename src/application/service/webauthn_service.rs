@@ -0,0 +1,491 @@
+use base64::Engine;
+use redis::AsyncCommands;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::application::{
+    config::Config,
+    constant::{WEBAUTHN_AUTHENTICATION_REDIS_KEY, WEBAUTHN_REGISTRATION_REDIS_KEY},
+    repository::{user_repository::UserRepositoryExt, webauthn_repository::WebAuthnRepositoryExt},
+    security::{
+        auth::AuthError,
+        secret,
+        webauthn::{self, BASE64URL, ClientData, RelyingParty, SUPPORTED_ALGORITHMS},
+    },
+    state::SharedState,
+};
+use crate::domain::entities::user::User;
+use crate::domain::entities::webauthn_credential::{NewWebAuthnCredential, WebAuthnCredential};
+
+/// Options of `navigator.credentials.create()`, shaped as `PublicKeyCredentialCreationOptionsJSON`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    /// Milliseconds.
+    pub timeout: u64,
+    pub attestation: &'static str,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+}
+
+/// Options of `navigator.credentials.get()`, shaped as `PublicKeyCredentialRequestOptionsJSON`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    /// Milliseconds.
+    pub timeout: u64,
+    pub user_verification: &'static str,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    /// User handle, the base64url encoded bytes of the user ID.
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: &'static str,
+    pub user_verification: &'static str,
+}
+
+/// Response of an authenticator to a registration ceremony, base64url encoded.
+#[derive(Debug)]
+pub struct AttestationResponse<'a> {
+    pub client_data_json: &'a str,
+    pub attestation_object: &'a str,
+}
+
+/// Response of an authenticator to an authentication ceremony, base64url encoded.
+#[derive(Debug)]
+pub struct AssertionResponse<'a> {
+    pub credential_id: &'a str,
+    pub client_data_json: &'a str,
+    pub authenticator_data: &'a str,
+    pub signature: &'a str,
+    pub user_handle: Option<&'a str>,
+}
+
+/// Starts the registration of a passkey for `user`, the challenge is kept until the ceremony completes.
+pub async fn start_registration(user: &User, state: &SharedState) -> Result<CreationOptions, AuthError> {
+    let challenge = secret::generate_token(32);
+    let exclude_credentials = state.list_webauthn_credentials(user.id)
+        .await?
+        .into_iter()
+        .map(|credential| descriptor(credential.credential_id))
+        .collect();
+
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.set_ex(registration_key(user.id), &challenge, state.config.webauthn_challenge_exp_seconds).await?;
+    drop(redis);
+
+    Ok(CreationOptions {
+        challenge,
+        rp: RelyingPartyEntity {
+            id: state.config.webauthn_rp_id.to_owned(),
+            name: state.config.webauthn_rp_name.to_owned(),
+        },
+        user: UserEntity {
+            id: BASE64URL.encode(user.id.as_bytes()),
+            name: user.username.to_owned(),
+            display_name: user.name.to_owned(),
+        },
+        pub_key_cred_params: SUPPORTED_ALGORITHMS.iter()
+            .map(|alg| CredentialParameters { typ: "public-key", alg: *alg })
+            .collect(),
+        timeout: state.config.webauthn_challenge_exp_seconds * 1000,
+        attestation: "none",
+        exclude_credentials,
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred",
+            user_verification: user_verification(&state.config),
+        },
+    })
+}
+
+/// Completes the registration started by [`start_registration`], storing the new credential.
+pub async fn finish_registration(
+    user: &User,
+    name: &str,
+    response: &AttestationResponse<'_>,
+    state: &SharedState,
+) -> Result<WebAuthnCredential, AuthError> {
+    let invalid = |message: &str| AuthError::InvalidWebAuthnResponse(message.to_owned());
+
+    let mut redis = state.cache.lock().await;
+    let challenge: Option<String> = redis.get_del(registration_key(user.id)).await?;
+    drop(redis);
+    let challenge = challenge.ok_or_else(|| invalid("registration not started or expired"))?;
+
+    let client_data_json = decode_base64url(response.client_data_json).ok_or_else(|| invalid("malformed clientDataJSON"))?;
+    let attestation_object = decode_base64url(response.attestation_object).ok_or_else(|| invalid("malformed attestationObject"))?;
+
+    let registration = webauthn::verify_registration(&client_data_json, &attestation_object, &challenge, &relying_party(&state.config))
+        .map_err(|e| invalid(&e.to_string()))?;
+
+    let credential_id = BASE64URL.encode(&registration.credential.credential_id);
+    if state.get_webauthn_credential(&credential_id).await?.is_some() {
+        return Err(invalid("credential already registered"))
+    }
+
+    let new_credential = NewWebAuthnCredential {
+        user_id: user.id,
+        credential_id,
+        public_key: registration.credential.public_key,
+        algorithm: registration.algorithm as i32,
+        sign_count: registration.sign_count as i64,
+        name: name.to_owned(),
+    };
+    let credential = state.create_webauthn_credential(&new_credential).await?;
+    tracing::info!("webauthn credential {} registered for user {}", credential.id, user.id);
+
+    Ok(credential)
+}
+
+/// Starts an authentication ceremony.
+///
+/// Without `identifier` the authenticator picks one of its discoverable credentials for this relying party.
+pub async fn start_authentication(identifier: Option<&str>, state: &SharedState) -> Result<RequestOptions, AuthError> {
+    let user = match identifier {
+        Some(identifier) => state.get_user_by_identifier(identifier).await?,
+        None => None,
+    };
+    let allow_credentials = match &user {
+        Some(user) => state.list_webauthn_credentials(user.id)
+            .await?
+            .into_iter()
+            .map(|credential| descriptor(credential.credential_id))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // The challenge comes back in the client data, it is the key of the pending ceremony.
+    let challenge = secret::generate_token(32);
+    let expected_user = user.map(|user| user.id.to_string()).unwrap_or_default();
+
+    let mut redis = state.cache.lock().await;
+    let _: () = redis.set_ex(authentication_key(&challenge), expected_user, state.config.webauthn_challenge_exp_seconds).await?;
+    drop(redis);
+
+    Ok(RequestOptions {
+        challenge,
+        rp_id: state.config.webauthn_rp_id.to_owned(),
+        timeout: state.config.webauthn_challenge_exp_seconds * 1000,
+        user_verification: user_verification(&state.config),
+        allow_credentials,
+    })
+}
+
+/// Completes an authentication ceremony, returning the owner of the credential.
+///
+/// Every failure is reported as wrong credentials, the detail is only logged.
+pub async fn finish_authentication(response: &AssertionResponse<'_>, state: &SharedState) -> Result<User, AuthError> {
+    let wrong_credentials = |reason: &str| {
+        tracing::error!("webauthn authentication failed: {}", reason);
+        AuthError::WrongCredentials
+    };
+
+    let client_data_json = decode_base64url(response.client_data_json).ok_or_else(|| wrong_credentials("malformed clientDataJSON"))?;
+    let authenticator_data = decode_base64url(response.authenticator_data).ok_or_else(|| wrong_credentials("malformed authenticatorData"))?;
+    let signature = decode_base64url(response.signature).ok_or_else(|| wrong_credentials("malformed signature"))?;
+    let client_data = ClientData::parse(&client_data_json).map_err(|e| wrong_credentials(&e.to_string()))?;
+
+    // The challenge is single use, whatever the outcome of the ceremony.
+    let mut redis = state.cache.lock().await;
+    let expected_user: Option<String> = redis.get_del(authentication_key(&client_data.challenge)).await?;
+    drop(redis);
+    let expected_user = expected_user.ok_or_else(|| wrong_credentials("unknown or expired challenge"))?;
+
+    let credential_id = decode_base64url(response.credential_id)
+        .map(|id| BASE64URL.encode(id))
+        .ok_or_else(|| wrong_credentials("malformed credential ID"))?;
+    let credential = state.get_webauthn_credential(&credential_id)
+        .await?
+        .ok_or_else(|| wrong_credentials("unknown credential"))?;
+
+    if !expected_user.is_empty() && expected_user != credential.user_id.to_string() {
+        return Err(wrong_credentials("credential of another user"))
+    }
+    if let Some(user_handle) = response.user_handle.filter(|user_handle| !user_handle.is_empty())
+        && decode_base64url(user_handle).as_deref() != Some(credential.user_id.as_bytes()) {
+        return Err(wrong_credentials("user handle mismatch"))
+    }
+
+    let sign_count = webauthn::verify_assertion(
+        &client_data_json,
+        &authenticator_data,
+        &signature,
+        &credential.public_key,
+        &client_data.challenge,
+        &relying_party(&state.config),
+    ).map_err(|e| wrong_credentials(&e.to_string()))?;
+
+    // Authenticators without a counter always report zero, otherwise it must increase.
+    let sign_count = sign_count as i64;
+    if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+        tracing::error!("signature counter of webauthn credential {} went backwards, possible cloned authenticator", credential.id);
+        return Err(AuthError::WrongCredentials)
+    }
+    state.update_webauthn_sign_count(credential.id, sign_count).await?;
+
+    let user = state.get_user_by_id(credential.user_id).await?;
+    if !user.active || user.email_verified_at.is_none() {
+        return Err(wrong_credentials("inactive or unverified user"))
+    }
+    tracing::info!("user {} authenticated with webauthn credential {}", user.id, credential.id);
+
+    Ok(user)
+}
+
+fn relying_party(config: &Config) -> RelyingParty<'_> {
+    RelyingParty {
+        id: &config.webauthn_rp_id,
+        origin: &config.webauthn_origin,
+        require_user_verification: config.webauthn_require_user_verification,
+    }
+}
+
+fn user_verification(config: &Config) -> &'static str {
+    if config.webauthn_require_user_verification { "required" } else { "preferred" }
+}
+
+fn descriptor(credential_id: String) -> CredentialDescriptor {
+    CredentialDescriptor { typ: "public-key", id: credential_id }
+}
+
+fn decode_base64url(value: &str) -> Option<Vec<u8>> {
+    BASE64URL.decode(value).ok()
+}
+
+fn registration_key(user_id: Uuid) -> String {
+    format!("{}.{}", WEBAUTHN_REGISTRATION_REDIS_KEY, user_id)
+}
+
+fn authentication_key(challenge: &str) -> String {
+    format!("{}.{}", WEBAUTHN_AUTHENTICATION_REDIS_KEY, challenge)
+}
+
+/// These tests need PostgreSQL and Redis, see [`crate::application::state::test_state`], run them with
+/// `cargo test -- --ignored`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::security::webauthn::SoftAuthenticator;
+    use crate::application::state::test_state;
+    use crate::domain::entities::user::NewUser;
+
+    async fn verified_user(state: &SharedState) -> User {
+        let name = format!("webauthn-{}", Uuid::new_v4().simple());
+        let new_user = NewUser {
+            name: name.to_owned(),
+            username: name.to_owned(),
+            email: format!("{}@example.com", name),
+            password_hash: String::new(),
+            roles: "user".to_owned(),
+        };
+        let user = state.create_user(&new_user).await.unwrap();
+        state.mark_email_verified(user.id).await.unwrap()
+    }
+
+    fn authenticator(state: &SharedState) -> SoftAuthenticator {
+        SoftAuthenticator::es256(&state.config.webauthn_rp_id, &state.config.webauthn_origin)
+    }
+
+    async fn register(user: &User, authenticator: &SoftAuthenticator, state: &SharedState) -> Result<WebAuthnCredential, AuthError> {
+        let options = start_registration(user, state).await?;
+        let (client_data_json, attestation_object) = authenticator.register(&options.challenge);
+        let response = AttestationResponse {
+            client_data_json: &BASE64URL.encode(client_data_json),
+            attestation_object: &BASE64URL.encode(attestation_object),
+        };
+        finish_registration(user, "passkey", &response, state).await
+    }
+
+    /// Base64url encoded assertion of `authenticator`, as posted by the browser.
+    struct Assertion {
+        credential_id: String,
+        client_data_json: String,
+        authenticator_data: String,
+        signature: String,
+        user_handle: Option<String>,
+    }
+
+    impl Assertion {
+        fn sign(authenticator: &mut SoftAuthenticator, challenge: &str, user: &User) -> Self {
+            let (client_data_json, authenticator_data, signature) = authenticator.sign(challenge);
+            Self {
+                credential_id: BASE64URL.encode(&authenticator.credential_id),
+                client_data_json: BASE64URL.encode(client_data_json),
+                authenticator_data: BASE64URL.encode(authenticator_data),
+                signature: BASE64URL.encode(signature),
+                user_handle: Some(BASE64URL.encode(user.id.as_bytes())),
+            }
+        }
+
+        fn response(&self) -> AssertionResponse<'_> {
+            AssertionResponse {
+                credential_id: &self.credential_id,
+                client_data_json: &self.client_data_json,
+                authenticator_data: &self.authenticator_data,
+                signature: &self.signature,
+                user_handle: self.user_handle.as_deref(),
+            }
+        }
+    }
+
+    async fn sign_in(identifier: Option<&str>, authenticator: &mut SoftAuthenticator, user: &User, state: &SharedState) -> Result<User, AuthError> {
+        let options = start_authentication(identifier, state).await?;
+        let assertion = Assertion::sign(authenticator, &options.challenge, user);
+        finish_authentication(&assertion.response(), state).await
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn registers_a_passkey_and_signs_in() {
+        let state = test_state().await;
+        let user = verified_user(&state).await;
+
+        let rp = (&state.config.webauthn_rp_id, &state.config.webauthn_origin);
+        for mut authenticator in [SoftAuthenticator::es256(rp.0, rp.1), SoftAuthenticator::ed25519(rp.0, rp.1)] {
+            let credential = register(&user, &authenticator, &state).await.unwrap();
+            assert_eq!(credential.user_id, user.id);
+            assert_eq!(credential.public_key, authenticator.cose_key());
+
+            // With the username, then with a discoverable credential.
+            assert_eq!(sign_in(Some(&user.username), &mut authenticator, &user, &state).await.unwrap().id, user.id);
+            assert_eq!(sign_in(None, &mut authenticator, &user, &state).await.unwrap().id, user.id);
+
+            let credential = state.get_webauthn_credential(&credential.credential_id).await.unwrap().unwrap();
+            assert_eq!(credential.sign_count, 2);
+        }
+
+        let options = start_registration(&user, &state).await.unwrap();
+        assert_eq!(options.exclude_credentials.len(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn challenges_are_single_use() {
+        let state = test_state().await;
+        let user = verified_user(&state).await;
+        let mut authenticator = authenticator(&state);
+
+        let options = start_registration(&user, &state).await.unwrap();
+        let (client_data_json, attestation_object) = authenticator.register(&options.challenge);
+        let response = AttestationResponse {
+            client_data_json: &BASE64URL.encode(client_data_json),
+            attestation_object: &BASE64URL.encode(attestation_object),
+        };
+        finish_registration(&user, "passkey", &response, &state).await.unwrap();
+        assert!(matches!(finish_registration(&user, "passkey", &response, &state).await, Err(AuthError::InvalidWebAuthnResponse(_))));
+
+        let options = start_authentication(None, &state).await.unwrap();
+        let assertion = Assertion::sign(&mut authenticator, &options.challenge, &user);
+        finish_authentication(&assertion.response(), &state).await.unwrap();
+        assert!(matches!(finish_authentication(&assertion.response(), &state).await, Err(AuthError::WrongCredentials)));
+
+        // A fresh signature over a consumed challenge is refused as well.
+        let assertion = Assertion::sign(&mut authenticator, &options.challenge, &user);
+        assert!(matches!(finish_authentication(&assertion.response(), &state).await, Err(AuthError::WrongCredentials)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn rejects_wrong_origin_relying_party_and_missing_user_verification() {
+        let state = test_state().await;
+        let user = verified_user(&state).await;
+
+        let mut authenticator = authenticator(&state);
+        authenticator.origin = "https://evil.example".to_owned();
+        assert!(matches!(register(&user, &authenticator, &state).await, Err(AuthError::InvalidWebAuthnResponse(_))));
+
+        let authenticator = SoftAuthenticator::es256("evil.example", &state.config.webauthn_origin);
+        assert!(matches!(register(&user, &authenticator, &state).await, Err(AuthError::InvalidWebAuthnResponse(_))));
+
+        let mut authenticator = self::authenticator(&state);
+        register(&user, &authenticator, &state).await.unwrap();
+
+        authenticator.origin = "https://evil.example".to_owned();
+        assert!(matches!(sign_in(None, &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+
+        authenticator.origin = state.config.webauthn_origin.to_owned();
+        authenticator.rp_id = "evil.example".to_owned();
+        assert!(matches!(sign_in(None, &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+
+        authenticator.rp_id = state.config.webauthn_rp_id.to_owned();
+        // Clear the user verified flag.
+        authenticator.flags &= !0x04;
+        assert!(matches!(sign_in(None, &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn rejects_a_counter_going_backwards() {
+        let state = test_state().await;
+        let user = verified_user(&state).await;
+        let mut authenticator = authenticator(&state);
+        register(&user, &authenticator, &state).await.unwrap();
+
+        authenticator.sign_count = 10;
+        sign_in(None, &mut authenticator, &user, &state).await.unwrap();
+
+        // A clone of the authenticator taken before the last sign in.
+        authenticator.sign_count = 5;
+        assert!(matches!(sign_in(None, &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+        authenticator.sign_count = 10;
+        assert!(matches!(sign_in(None, &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+
+        sign_in(None, &mut authenticator, &user, &state).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs PostgreSQL and Redis"]
+    async fn rejects_a_credential_of_another_user() {
+        let state = test_state().await;
+        let user = verified_user(&state).await;
+        let other = verified_user(&state).await;
+        let mut authenticator = authenticator(&state);
+        register(&user, &authenticator, &state).await.unwrap();
+
+        // User handle of another account.
+        let options = start_authentication(None, &state).await.unwrap();
+        let assertion = Assertion::sign(&mut authenticator, &options.challenge, &other);
+        assert!(matches!(finish_authentication(&assertion.response(), &state).await, Err(AuthError::WrongCredentials)));
+
+        // Ceremony started for another account.
+        assert!(matches!(sign_in(Some(&other.username), &mut authenticator, &user, &state).await, Err(AuthError::WrongCredentials)));
+
+        // The credential is left untouched by the failures.
+        assert_eq!(sign_in(Some(&user.username), &mut authenticator, &user, &state).await.unwrap().id, user.id);
+    }
+}
@@ -1,7 +1,9 @@
 use std::sync::{Arc};
 use tokio::sync::Mutex;
 use crate::application::config::Config;
+use crate::application::security::password::HashingPool;
 use crate::infra::database::DatabasePool;
+use crate::infra::mail::Mailer;
 
 pub type SharedState = Arc<AppState>;
 
@@ -9,4 +11,36 @@ pub struct AppState {
     pub config: Config,
     pub db_pool: DatabasePool,
     pub cache: Mutex<redis::aio::MultiplexedConnection>,
-}
\ No newline at end of file
+    pub mailer: Mailer,
+    pub hasher: HashingPool,
+}
+
+/// State of tests needing PostgreSQL and Redis, reached through `DATABASE_URL`, `REDIS_HOST` and `REDIS_PORT`
+/// (from the environment or `.env`), the database is migrated first.
+#[cfg(test)]
+pub(crate) async fn test_state() -> SharedState {
+    dotenvy::dotenv().ok();
+    let mut config = crate::application::config::test_config();
+    if let Ok(database_url) = std::env::var("DATABASE_URL") {
+        config.database_url = database_url;
+    }
+    if let Ok(redis_host) = std::env::var("REDIS_HOST") {
+        config.redis_host = redis_host;
+    }
+    if let Some(redis_port) = std::env::var("REDIS_PORT").ok().and_then(|port| port.parse().ok()) {
+        config.redis_port = redis_port;
+    }
+    config.mail_outbox_dir = std::env::temp_dir().join("axum-restapi-outbox").to_string_lossy().into_owned();
+
+    let db_pool = crate::infra::database::load(&config).await;
+    crate::infra::database::migrate(&db_pool).await;
+    let cache = crate::infra::cache::load(&config).await;
+
+    Arc::new(AppState {
+        mailer: crate::infra::mail::load(&config),
+        hasher: HashingPool::new(&config),
+        config,
+        db_pool,
+        cache: Mutex::new(cache),
+    })
+}
@@ -1 +1,6 @@
-pub mod user;
\ No newline at end of file
+pub mod user;
+pub mod recovery_code;
+pub mod api_key;
+pub mod session;
+pub mod oauth_client;
+pub mod webauthn_credential;
\ No newline at end of file
@@ -0,0 +1,65 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, FromRow};
+
+pub const GRANT_AUTHORIZATION_CODE: &str = "authorization_code";
+pub const GRANT_REFRESH_TOKEN: &str = "refresh_token";
+pub const GRANT_CLIENT_CREDENTIALS: &str = "client_credentials";
+pub const GRANT_DEVICE_CODE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct OAuthClient {
+    pub id: Uuid,
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    /// Comma separated, redirect URIs must match exactly.
+    pub redirect_uris: String,
+    /// Comma separated.
+    pub grant_types: String,
+    /// Comma separated scopes the client may request.
+    pub scopes: String,
+    /// Comma separated roles of the tokens issued by the `client_credentials` grant.
+    pub roles: String,
+    pub created_at: Option<NaiveDateTime>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl OAuthClient {
+    /// Confidential clients authenticate with a secret, public clients rely on PKCE only.
+    pub fn is_confidential(&self) -> bool {
+        self.client_secret_hash.is_some()
+    }
+
+    pub fn allows_grant(&self, grant_type: &str) -> bool {
+        split_list(&self.grant_types).any(|allowed| allowed == grant_type)
+    }
+
+    pub fn allows_redirect_uri(&self, redirect_uri: &str) -> bool {
+        split_list(&self.redirect_uris).any(|allowed| allowed == redirect_uri)
+    }
+
+    pub fn allows_scope(&self, scope: &str) -> bool {
+        split_list(&self.scopes).any(|allowed| allowed == scope)
+    }
+
+    pub fn scopes(&self) -> Vec<String> {
+        split_list(&self.scopes).map(str::to_owned).collect()
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+#[derive(Debug)]
+pub struct NewOAuthClient {
+    pub client_id: String,
+    pub client_secret_hash: Option<String>,
+    pub name: String,
+    pub redirect_uris: String,
+    pub grant_types: String,
+    pub scopes: String,
+    pub roles: String,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// A signed-in device, one per refresh token family.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Refresh token family ID, the `fid` claim of the session tokens.
+    pub id: String,
+    pub user_id: String,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    /// Expiry of the current refresh token, the session ends with it.
+    #[serde(skip_serializing)]
+    pub refresh_exp: usize,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+}
@@ -12,6 +12,19 @@ pub struct User {
     pub password_hash: String,
     pub active: bool,
     pub roles: String,
+    pub email_verified_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<NaiveDateTime>,
     pub created_at: Option<NaiveDateTime>,
     pub updated_at: Option<NaiveDateTime>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+pub struct NewUser {
+    pub name: String,
+    pub username: String,
+    pub email: String,
+    pub password_hash: String,
+    pub roles: String,
+}
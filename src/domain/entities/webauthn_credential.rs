@@ -0,0 +1,32 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Uuid, FromRow};
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct WebAuthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// Credential ID chosen by the authenticator, base64url encoded.
+    pub credential_id: String,
+    /// COSE_Key form of the public key.
+    #[serde(skip_serializing)]
+    pub public_key: Vec<u8>,
+    /// COSE algorithm identifier of the public key.
+    pub algorithm: i32,
+    /// Last signature counter, a counter going backwards reveals a cloned authenticator.
+    #[serde(skip_serializing)]
+    pub sign_count: i64,
+    pub name: String,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug)]
+pub struct NewWebAuthnCredential {
+    pub user_id: Uuid,
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: i64,
+    pub name: String,
+}
@@ -42,7 +42,7 @@ pub enum DatabaseError {
 }
 
 pub async fn load(config: &Config) -> DatabasePool {
-    Database::connect(&config).await.unwrap_or_else(|e| {
+    Database::connect(config).await.unwrap_or_else(|e| {
         tracing::error!("{}", e);
         std::process::exit(1);
     })
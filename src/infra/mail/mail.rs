@@ -0,0 +1,57 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::application::config::Config;
+use crate::infra::mail::{
+    outbox::OutboxTransport,
+    smtp::{SmtpOption, SmtpTransport},
+};
+
+pub type Mailer = Arc<dyn MailTransport>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailMessage {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError>;
+}
+
+#[derive(Debug, Error)]
+pub enum MailError {
+    #[error("invalid mail address: {0}")]
+    InvalidAddress(String),
+    #[error(transparent)]
+    BuildError(#[from] lettre::error::Error),
+    #[error(transparent)]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+pub fn load(config: &Config) -> Mailer {
+    match config.mail_transport.as_str() {
+        "smtp" => {
+            let option = SmtpOption {
+                host: config.smtp_host.to_owned(),
+                port: config.smtp_port,
+                username: config.smtp_username.to_owned(),
+                password: config.smtp_password.to_owned(),
+            };
+            Arc::new(SmtpTransport::open(option))
+        }
+        "outbox" => Arc::new(OutboxTransport::open(&config.mail_outbox_dir)),
+        transport => {
+            tracing::error!("unknown mail transport: {}", transport);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod mail;
+mod smtp;
+mod outbox;
+
+pub use outbox::OutboxTransport;
+pub use mail::{
+    MailMessage, MailTransport, MailError, Mailer, load
+};
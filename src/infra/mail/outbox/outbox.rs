@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+use async_trait::async_trait;
+use crate::infra::mail::{MailError, MailMessage, MailTransport};
+
+/// Writes every message as a JSON file instead of delivering it, for development and tests.
+pub struct OutboxTransport {
+    dir: PathBuf,
+}
+
+impl OutboxTransport {
+    pub fn open(dir: &str) -> Self {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            tracing::error!("could not create mail outbox {}: {}", dir, e);
+            std::process::exit(1);
+        }
+
+        tracing::info!("using outbox mail transport: {}", dir);
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Messages written so far, in sending order.
+    pub fn messages(&self) -> Result<Vec<MailMessage>, MailError> {
+        let mut paths = std::fs::read_dir(&self.dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.extension().is_some_and(|extension| extension == "json"));
+        paths.sort();
+
+        paths.iter()
+            .map(|path| Ok(serde_json::from_slice(&std::fs::read(path)?)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl MailTransport for OutboxTransport {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        // Prefix with the timestamp so messages sort in sending order.
+        let file_name = format!("{}-{}.json", chrono::Utc::now().timestamp_micros(), uuid::Uuid::new_v4());
+        let content = serde_json::to_vec_pretty(message)?;
+        tokio::fs::write(self.dir.join(file_name), content).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(subject: &str) -> MailMessage {
+        MailMessage {
+            from: "noreply@example.com".to_owned(),
+            to: "alice@example.com".to_owned(),
+            subject: subject.to_owned(),
+            body: "Hi Alice".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sent_messages_are_read_back_in_order() {
+        let dir = std::env::temp_dir().join(format!("outbox-{}", uuid::Uuid::new_v4()));
+        let outbox = OutboxTransport::open(dir.to_str().unwrap());
+
+        outbox.send(&message("first")).await.unwrap();
+        outbox.send(&message("second")).await.unwrap();
+
+        let messages = outbox.messages().unwrap();
+        let subjects: Vec<&str> = messages.iter().map(|message| message.subject.as_str()).collect();
+        assert_eq!(subjects, ["first", "second"]);
+        assert_eq!(messages[0].to, "alice@example.com");
+        assert_eq!(messages[0].body, "Hi Alice");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
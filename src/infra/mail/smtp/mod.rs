@@ -0,0 +1,4 @@
+#[allow(clippy::module_inception)]
+mod smtp;
+
+pub use smtp::{SmtpTransport, SmtpOption};
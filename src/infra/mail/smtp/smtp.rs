@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::header::ContentType,
+    transport::smtp::authentication::Credentials,
+};
+use crate::infra::mail::{MailError, MailMessage, MailTransport};
+
+pub struct SmtpOption {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+}
+
+pub struct SmtpTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn open(option: SmtpOption) -> Self {
+        let builder = match AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&option.host) {
+            Ok(builder) => builder,
+            Err(e) => {
+                tracing::error!("could not open smtp relay {}: {}", option.host, e);
+                std::process::exit(1);
+            }
+        };
+
+        let builder = builder.port(option.port);
+        let builder = match option.username.is_empty() {
+            true => builder,
+            false => builder.credentials(Credentials::new(option.username, option.password)),
+        };
+
+        tracing::info!("using smtp mail transport: {}:{}", option.host, option.port);
+        Self {
+            transport: builder.build(),
+        }
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpTransport {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        let email = Message::builder()
+            .from(message.from.parse().map_err(|_| MailError::InvalidAddress(message.from.to_owned()))?)
+            .to(message.to.parse().map_err(|_| MailError::InvalidAddress(message.to.to_owned()))?)
+            .subject(&message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.to_owned())?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
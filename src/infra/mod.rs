@@ -1,2 +1,3 @@
 pub mod database;
-pub mod cache;
\ No newline at end of file
+pub mod cache;
+pub mod mail;
\ No newline at end of file
@@ -1,5 +1,5 @@
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use axum_restapi::application::app;
+use axum_restapi::application::{app, cli};
 
 #[tokio::main]
 async fn main() {
@@ -18,5 +18,11 @@ async fn main() {
 
     tracing::info!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
+    // Operator commands, e.g. JWT key rotation, run instead of the server.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     app::run().await
 }